@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Geometry, Instruction, parse_string, ParseError, REGISTERS};
+use crate::ocr::{CELL_WIDTH, glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// Where `.draw` parks the sprite for a dark pixel, far enough from every column unless the
+/// sprite is wider than 9 pixels.
+const DARK: i32 = -5;
+
+#[derive(Debug, PartialEq)]
+pub enum AsmError {
+    Parse(ParseError),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    InvalidCount(String),
+    UnterminatedRepeat,
+    UnexpectedEnd,
+    UnknownDirective(String),
+    InvalidText(String),
+    UnknownLetter(char),
+    TextTooLong(String),
+    /// The first pixel is lit by a sprite at least 3 pixels wide, as x starts at 1
+    FirstPixelDark(String),
+    /// The first pixel is dark under a sprite narrower than 3 pixels
+    FirstPixelLit(String),
+    DrawNotAtStart,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::Parse(e) => write!(f, "{}", e),
+            AsmError::InvalidLabel(s) => write!(f, "invalid label {}", s),
+            AsmError::DuplicateLabel(s) => write!(f, "duplicate label {}", s),
+            AsmError::InvalidCount(s) => write!(f, "invalid repeat count {}", s),
+            AsmError::UnterminatedRepeat => write!(f, "repeat without end"),
+            AsmError::UnexpectedEnd => write!(f, "end without repeat"),
+            AsmError::UnknownDirective(s) => write!(f, "unknown directive {}", s),
+            AsmError::InvalidText(s) => write!(f, "expected quoted text, got {}", s),
+            AsmError::UnknownLetter(c) => write!(f, "no glyph for {:?}", c),
+            AsmError::TextTooLong(s) => write!(f, "{} does not fit on the screen", s),
+            AsmError::FirstPixelDark(s) => write!(f, "{} starts with a dark pixel, but the first pixel is always lit", s),
+            AsmError::FirstPixelLit(s) => write!(f, "{} starts with a lit pixel, but the sprite is too narrow to reach it", s),
+            AsmError::DrawNotAtStart => write!(f, ".draw must come before any instruction"),
+        }
+    }
+}
+
+/// An assembled program, with the instruction index every label points to.
+#[derive(Debug, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub labels: BTreeMap<String, usize>,
+}
+
+/// Removes a `;` comment, leaving semicolons inside quotes alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_label(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !REGISTERS.contains(&name)
+}
+
+/// Instructions that draw `text` on a fresh screen of the given geometry, one cycle each.
+///
+/// Every cycle moves the sprite onto the next pixel if it has to be lit, or away from it.
+fn draw(text: &str, geometry: Geometry) -> Result<Vec<String>, AsmError> {
+    let Geometry { width, height, sprite_width } = geometry;
+    let glyphs = text.chars()
+        .map(|c| if c == ' ' { Ok(None) } else { glyph(c).map(Some).ok_or(AsmError::UnknownLetter(c)) })
+        .collect::<Result<Vec<_>, AsmError>>()?;
+    if glyphs.len() > width / CELL_WIDTH || height < GLYPH_HEIGHT {
+        return Err(AsmError::TextTooLong(text.to_string()));
+    }
+    let lit = |pixel: usize| {
+        let (row, column) = (pixel / width, pixel % width);
+        let (cell, dx) = (column / CELL_WIDTH, column % CELL_WIDTH);
+        row < GLYPH_HEIGHT && dx < GLYPH_WIDTH && glyphs.get(cell).copied().flatten()
+            .is_some_and(|g| g[row].as_bytes()[dx] == b'#')
+    };
+    match (lit(0), geometry.covers(0, 1)) {
+        (false, true) => return Err(AsmError::FirstPixelDark(text.to_string())),
+        (true, false) => return Err(AsmError::FirstPixelLit(text.to_string())),
+        _ => {}
+    }
+    let dark = DARK.min(-1 - sprite_width as i32 / 2);
+
+    let mut x = 1;
+    let mut lines = Vec::new();
+    for pixel in 1..width * height {
+        // Set during the cycle drawing the previous pixel
+        let target = if lit(pixel) { (pixel % width) as i32 } else { dark };
+        if target == x {
+            lines.push(String::from("noop"));
+        } else {
+            lines.push(format!("set x {}", target));
+            x = target;
+        }
+    }
+    lines.push(String::from("noop"));
+    Ok(lines)
+}
+
+/// Assembles a program for a screen of the given geometry, failing with the line number of the
+/// first error.
+///
+/// Besides instructions, a line can hold `label:` definitions, `repeat N` and `end` around a
+/// block to copy it N times, a `.draw "TEXT"` directive, and a `;` comment. Labels can be
+/// used wherever a value is expected and stand for the index of the following instruction.
+pub fn assemble(source: &str, geometry: Geometry) -> Result<Program, (usize, AsmError)> {
+    // (line, instruction) with the repeats expanded and the labels not yet resolved
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut labels = BTreeMap::new();
+    // (line, count, first instruction) of the open repeat blocks
+    let mut repeats: Vec<(usize, usize, usize)> = Vec::new();
+
+    for (n, l) in source.lines().enumerate().map(|(n, l)| (n + 1, l)) {
+        let mut l = strip_comment(l).trim();
+        while let Some((label, rest)) = l.split_once(':') {
+            let label = label.trim();
+            if label.contains('"') {
+                break;
+            }
+            if !is_label(label) {
+                return Err((n, AsmError::InvalidLabel(label.to_string())));
+            }
+            if labels.insert(label.to_string(), lines.len()).is_some() {
+                return Err((n, AsmError::DuplicateLabel(label.to_string())));
+            }
+            l = rest.trim();
+        }
+
+        let words: Vec<&str> = l.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["repeat", count] => match count.parse::<usize>() {
+                Ok(count) => repeats.push((n, count, lines.len())),
+                Err(_) => return Err((n, AsmError::InvalidCount(count.to_string()))),
+            },
+            ["end"] => {
+                let (_, count, first) = repeats.pop().ok_or((n, AsmError::UnexpectedEnd))?;
+                let block = lines.split_off(first);
+                for _ in 0..count {
+                    lines.extend(block.iter().cloned());
+                }
+            }
+            [".draw", ..] => {
+                let text = l[".draw".len()..].trim();
+                let text = text.strip_prefix('"')
+                    .and_then(|t| t.strip_suffix('"'))
+                    .ok_or_else(|| (n, AsmError::InvalidText(text.to_string())))?;
+                if !lines.is_empty() {
+                    return Err((n, AsmError::DrawNotAtStart));
+                }
+                lines.extend(draw(text, geometry).map_err(|e| (n, e))?.into_iter().map(|i| (n, i)));
+            }
+            [directive, ..] if directive.starts_with('.') => {
+                return Err((n, AsmError::UnknownDirective(directive.to_string())));
+            }
+            _ => lines.push((n, l.to_string())),
+        }
+    }
+    if let Some((n, _, _)) = repeats.pop() {
+        return Err((n, AsmError::UnterminatedRepeat));
+    }
+
+    let mut instructions = Vec::new();
+    for (n, l) in lines {
+        let resolved: Vec<String> = l.split_whitespace()
+            .enumerate()
+            .map(|(i, word)| match labels.get(word) {
+                Some(index) if i > 0 => index.to_string(),
+                _ => word.to_string(),
+            })
+            .collect();
+        instructions.push(parse_string(&resolved.join(" ")).map_err(|e| (n, AsmError::Parse(e)))?);
+    }
+    Ok(Program { instructions, labels })
+}
+
+/// Prints a program one instruction per line, with the labels and the cycles each one runs in.
+///
+/// Each instruction is printed as `parse_string` reads it, and the whole output assembles
+/// back to the same program. It also parses back with `parse_lines`, which skips the labels
+/// and comments.
+pub fn disassemble(program: &Program) -> String {
+    let mut out = String::new();
+    let mut cycle = 1;
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        for (label, _) in program.labels.iter().filter(|(_, index)| **index == i) {
+            out.push_str(&format!("{}:\n", label));
+        }
+        let cycles = instruction.cycles();
+        let annotation = if cycles == 1 {
+            format!("cycle {}", cycle)
+        } else {
+            format!("cycles {}-{}", cycle, cycle + cycles - 1)
+        };
+        out.push_str(&format!("    {:<12} ; {}\n", instruction.to_string(), annotation));
+        cycle += cycles;
+    }
+    for (label, _) in program.labels.iter().filter(|(_, index)| **index == program.instructions.len()) {
+        out.push_str(&format!("{}:\n", label));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::{Cpu, Geometry, parse_file, parse_lines, parse_string, ParseError};
+    use crate::asm::{assemble, AsmError, disassemble, Program};
+
+    #[test]
+    fn test_assemble() {
+        let program = assemble("\
+; count to three
+start:  set y 0
+        repeat 3
+            add y 1   ; one more
+        end
+done:   set z done
+        set w start
+", Geometry::default()).unwrap();
+        assert_eq!(program.instructions.len(), 6);
+        assert_eq!(program.labels.get("done"), Some(&4));
+        let mut cpu = Cpu::new(Geometry::default());
+        cpu.run(&program.instructions);
+        assert_eq!(cpu.registers, [1, 3, 4, 0]);
+    }
+
+    #[test]
+    fn test_nested_repeat() {
+        let program = assemble("repeat 2\nnoop\nrepeat 3\naddx 1\nend\nend", Geometry::default()).unwrap();
+        assert_eq!(program.instructions.len(), 8);
+        assert_eq!(program.instructions[4], parse_string("noop").unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(assemble("noop\nrepeat 2\nnoop", Geometry::default()), Err((2, AsmError::UnterminatedRepeat)));
+        assert_eq!(assemble("end", Geometry::default()), Err((1, AsmError::UnexpectedEnd)));
+        assert_eq!(assemble("a:\na: noop", Geometry::default()), Err((2, AsmError::DuplicateLabel(String::from("a")))));
+        assert_eq!(assemble("x: noop", Geometry::default()), Err((1, AsmError::InvalidLabel(String::from("x")))));
+        assert_eq!(assemble("noop\naddx here", Geometry::default()), Err((2, AsmError::Parse(ParseError::InvalidValue(String::from("here"))))));
+        assert_eq!(assemble("repeat many", Geometry::default()), Err((1, AsmError::InvalidCount(String::from("many")))));
+        assert_eq!(assemble(".org 3", Geometry::default()), Err((1, AsmError::UnknownDirective(String::from(".org")))));
+        assert!(assemble(".draw \"HAL\"", Geometry::default()).is_ok());
+        assert_eq!(assemble(".draw \"ACE\"", Geometry::default()), Err((1, AsmError::FirstPixelDark(String::from("ACE")))));
+        assert_eq!(assemble(".draw \"H!\"", Geometry::default()), Err((1, AsmError::UnknownLetter('!'))));
+        assert_eq!(assemble(".draw \"HELLOHELLO\"", Geometry::default()), Err((1, AsmError::TextTooLong(String::from("HELLOHELLO")))));
+        assert_eq!(assemble("noop\n.draw \"H\"", Geometry::default()), Err((2, AsmError::DrawNotAtStart)));
+    }
+
+    #[test]
+    fn test_draw() {
+        for text in ["HELP", "EFUGLPAP", "R U OK", "BZ"] {
+            let program = assemble(&format!(".draw \"{}\" ; semicolons; in \"comments\"", text), Geometry::default()).unwrap();
+            let mut cpu = Cpu::new(Geometry::default());
+            cpu.run(&program.instructions);
+            assert_eq!(cpu.cycle, 241);
+            assert_eq!(cpu.crt.read().unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn test_draw_geometry() {
+        for (geometry, text) in [
+            (Geometry { width: 50, height: 6, sprite_width: 3 }, "HELLO HAL"),
+            (Geometry { width: 48, height: 6, sprite_width: 1 }, "ACE"),
+            (Geometry { width: 40, height: 7, sprite_width: 12 }, "HELP"),
+        ] {
+            let program = assemble(&format!(".draw \"{}\"", text), geometry).unwrap();
+            let mut cpu = Cpu::new(geometry);
+            cpu.run(&program.instructions);
+            assert_eq!(cpu.crt.read().unwrap(), text);
+        }
+        let narrow = Geometry { sprite_width: 1, ..Geometry::default() };
+        assert_eq!(assemble(".draw \"HAL\"", narrow), Err((1, AsmError::FirstPixelLit(String::from("HAL")))));
+        let low = Geometry { height: 5, ..Geometry::default() };
+        assert_eq!(assemble(".draw \"HAL\"", low), Err((1, AsmError::TextTooLong(String::from("HAL")))));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = assemble("start: addx 3\nnoop\nloop: repeat 2\nset x 4\nend\nmov y x\nfinish:", Geometry::default()).unwrap();
+        assert_eq!(disassemble(&program), "\
+start:
+    addx 3       ; cycles 1-2
+    noop         ; cycle 3
+loop:
+    set x 4      ; cycle 4
+    set x 4      ; cycle 5
+    mov y x      ; cycle 6
+finish:
+");
+        assert_eq!(assemble(&disassemble(&program), Geometry::default()), Ok(program));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let program = Program { instructions: parse_file("input.txt", 0).unwrap(), labels: Default::default() };
+        let listing = disassemble(&program);
+        assert_eq!(parse_lines(listing.lines().map(String::from), 0).as_ref(), Ok(&program.instructions));
+        assert_eq!(assemble(&listing, Geometry::default()), Ok(program));
+
+        let program = assemble("start: addx 3\nnoop\nloop: repeat 2\nset x 4\nend\nfinish:", Geometry::default()).unwrap();
+        assert_eq!(parse_lines(disassemble(&program).lines().map(String::from), 0), Ok(program.instructions));
+        assert_eq!(assemble(&fs::read_to_string("test.txt").unwrap(), Geometry::default()).unwrap().instructions, parse_file("test.txt", 0).unwrap());
+    }
+}
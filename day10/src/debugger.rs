@@ -0,0 +1,252 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::{Cpu, Geometry, Instruction, REGISTERS, X};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    /// Stop before cycle N runs
+    Cycle(i32),
+    /// Stop when a register becomes a value
+    Register(usize, i32),
+    /// Stop before the instruction with this index starts
+    Instruction(usize),
+}
+
+impl Breakpoint {
+    /// Parses `cycle N`, `pc N` or `REGISTER N`.
+    fn parse(kind: &str, value: &str) -> Option<Breakpoint> {
+        match kind {
+            "cycle" => Some(Breakpoint::Cycle(value.parse().ok()?)),
+            "pc" => Some(Breakpoint::Instruction(value.parse().ok()?)),
+            r => Some(Breakpoint::Register(REGISTERS.iter().position(|n| *n == r)?, value.parse().ok()?)),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Cycle(c) => write!(f, "cycle {}", c),
+            Breakpoint::Register(r, v) => write!(f, "{} {}", REGISTERS[*r], v),
+            Breakpoint::Instruction(pc) => write!(f, "pc {}", pc),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Step,
+    Breakpoint(usize),
+    Halted,
+}
+
+/// Runs a program cycle by cycle, stopping at breakpoints.
+pub struct Debugger {
+    cpu: Cpu,
+    program: Vec<Instruction>,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<Instruction>, geometry: Geometry) -> Debugger {
+        Debugger { cpu: Cpu::new(geometry), program, breakpoints: Vec::new() }
+    }
+
+    pub fn add_breakpoint(&mut self, b: Breakpoint) -> usize {
+        self.breakpoints.push(b);
+        self.breakpoints.len() - 1
+    }
+
+    /// Runs a single cycle.
+    pub fn step(&mut self) -> Stop {
+        if self.cpu.tick(&self.program) {
+            Stop::Step
+        } else {
+            Stop::Halted
+        }
+    }
+
+    /// Runs at least one cycle, and then until a breakpoint is hit or the program finishes.
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            let registers = self.cpu.registers;
+            if !self.cpu.tick(&self.program) {
+                return Stop::Halted;
+            }
+            if let Some(i) = self.breakpoints.iter().position(|b| self.hit(b, &registers)) {
+                return Stop::Breakpoint(i);
+            }
+        }
+    }
+
+    /// Runs until cycle N is about to run, ignoring breakpoints.
+    pub fn run_to_cycle(&mut self, cycle: i32) -> Stop {
+        while self.cpu.cycle < cycle {
+            if !self.cpu.tick(&self.program) {
+                return Stop::Halted;
+            }
+        }
+        Stop::Step
+    }
+
+    fn hit(&self, b: &Breakpoint, previous: &[i32; 4]) -> bool {
+        match b {
+            Breakpoint::Cycle(c) => self.cpu.cycle == *c,
+            Breakpoint::Register(r, v) => self.cpu.registers[*r] == *v && previous[*r] != *v,
+            Breakpoint::Instruction(pc) => self.cpu.pc == *pc && self.cpu.elapsed == 0,
+        }
+    }
+
+    /// Registers, sprite and the part of the current CRT line drawn so far.
+    pub fn status(&self) -> String {
+        let cpu = &self.cpu;
+        let mut out = format!("cycle {}, ", cpu.cycle);
+        match self.program.get(cpu.pc) {
+            Some(i) => out.push_str(&format!("pc {} ({}, cycle {} of {})\n", cpu.pc, i, cpu.elapsed + 1, i.cycles())),
+            None => out.push_str("halted\n"),
+        }
+        let registers: Vec<String> = REGISTERS.iter()
+            .zip(cpu.registers.iter())
+            .map(|(n, v)| format!("{}={}", n, v))
+            .collect();
+        out.push_str(&format!("{}\n", registers.join(" ")));
+
+        let sprite: String = (0..cpu.crt.geometry.width as i32)
+            .map(|column| if cpu.crt.covers(column, cpu.registers[X]) { '#' } else { '.' })
+            .collect();
+        out.push_str(&format!("Sprite: {}\n", sprite));
+        let line: String = cpu.crt.scanline(cpu.cycle).iter().collect();
+        out.push_str(&format!("CRT:    {}\n", line));
+        out
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Step => self.status(),
+            Stop::Breakpoint(i) => format!("breakpoint {} ({})\n{}", i, self.breakpoints[i], self.status()),
+            Stop::Halted => format!("program finished\n{}", self.status()),
+        }
+    }
+
+    /// Runs one debugger command and returns what it prints.
+    pub fn execute(&mut self, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["step" | "s"] => {
+                let stop = self.step();
+                self.report(stop)
+            }
+            ["step" | "s", n] => match n.parse::<usize>() {
+                Ok(n) => {
+                    let mut stop = Stop::Step;
+                    for _ in 0..n {
+                        stop = self.step();
+                        if stop == Stop::Halted {
+                            break;
+                        }
+                    }
+                    self.report(stop)
+                }
+                Err(_) => format!("invalid count {}\n", n),
+            },
+            ["continue" | "c"] => {
+                let stop = self.cont();
+                self.report(stop)
+            }
+            ["run-to-cycle", n] => match n.parse::<i32>() {
+                Ok(n) => {
+                    let stop = self.run_to_cycle(n);
+                    self.report(stop)
+                }
+                Err(_) => format!("invalid cycle {}\n", n),
+            },
+            ["break", kind, value] => match Breakpoint::parse(kind, value) {
+                Some(b) => format!("breakpoint {} at {}\n", self.add_breakpoint(b), b),
+                None => format!("invalid breakpoint {} {}\n", kind, value),
+            },
+            ["breakpoints"] => self.breakpoints.iter()
+                .enumerate()
+                .map(|(i, b)| format!("{}: {}\n", i, b))
+                .collect(),
+            ["status"] => self.status(),
+            _ => String::from("commands: step [N], continue, run-to-cycle N, break cycle|pc|REGISTER N, breakpoints, status, quit\n"),
+        }
+    }
+
+    pub fn run(&mut self) {
+        print!("{}(debug) ", self.status());
+        io::stdout().flush().unwrap();
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if line.trim() == "quit" {
+                break;
+            }
+            print!("{}(debug) ", self.execute(&line));
+            io::stdout().flush().unwrap();
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Geometry, parse_file};
+    use crate::debugger::{Breakpoint, Debugger, Stop};
+
+    fn debugger() -> Debugger {
+        Debugger::new(parse_file("test.txt", 0).unwrap(), Geometry::default())
+    }
+
+    #[test]
+    fn test_step() {
+        let mut d = debugger();
+        assert_eq!(d.step(), Stop::Step);
+        assert_eq!(d.step(), Stop::Step);
+        assert_eq!(d.cpu.cycle, 3);
+        assert_eq!(d.cpu.registers[0], 16);
+        assert_eq!(d.execute("step 3"), "\
+cycle 6, pc 2 (addx 6, cycle 2 of 2)
+x=5 y=0 z=0 w=0
+Sprite: ....###.................................
+CRT:    ##..#
+");
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut d = debugger();
+        d.add_breakpoint(Breakpoint::Cycle(20));
+        d.add_breakpoint(Breakpoint::Register(0, 21));
+        assert_eq!(d.cont(), Stop::Breakpoint(1));
+        assert_eq!((d.cpu.cycle, d.cpu.registers[0]), (19, 21));
+        assert_eq!(d.cont(), Stop::Breakpoint(0));
+        assert_eq!(d.cpu.cycle, 20);
+        d.breakpoints.clear();
+        d.add_breakpoint(Breakpoint::Instruction(100));
+        assert_eq!(d.cont(), Stop::Breakpoint(0));
+        assert_eq!((d.cpu.pc, d.cpu.elapsed), (100, 0));
+        d.breakpoints.clear();
+        assert_eq!(d.cont(), Stop::Halted);
+        assert_eq!(d.cpu.cycle, 241);
+    }
+
+    #[test]
+    fn test_run_to_cycle() {
+        let mut d = debugger();
+        d.add_breakpoint(Breakpoint::Cycle(10));
+        assert_eq!(d.run_to_cycle(41), Stop::Step);
+        assert_eq!(d.cpu.crt.buf[..40].iter().collect::<String>(), "##..##..##..##..##..##..##..##..##..##..");
+        assert!(d.execute("run-to-cycle 45").ends_with("CRT:    ###.\n"));
+        assert_eq!(d.execute("run-to-cycle 500").lines().next(), Some("program finished"));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut d = debugger();
+        assert_eq!(d.execute("break x 21"), "breakpoint 0 at x 21\n");
+        assert_eq!(d.execute("break pc 3"), "breakpoint 1 at pc 3\n");
+        assert_eq!(d.execute("break q 3"), "invalid breakpoint q 3\n");
+        assert_eq!(d.execute("breakpoints"), "0: x 21\n1: pc 3\n");
+        assert!(d.execute("continue").starts_with("breakpoint 1 (pc 3)\ncycle 7, pc 3 (addx -3, cycle 1 of 2)"));
+    }
+}
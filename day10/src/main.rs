@@ -1,77 +1,341 @@
-use std::env;
+use std::{env, fmt, fs};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
-use crate::Instruction::{AddX, Noop};
+use crate::asm::{assemble, disassemble, Program};
+use crate::debugger::Debugger;
+use crate::ocr::UnknownGlyph;
 
-enum Instruction {
-    AddX(i32),
+mod asm;
+mod debugger;
+mod ocr;
+
+/// Register names, `x` being the one the puzzle CPU has and the CRT sprite follows.
+const REGISTERS: [&str; 4] = ["x", "y", "z", "w"];
+const X: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Opcode {
     Noop,
+    AddX,
+    Add,
+    Set,
+    Mov,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperandKind {
+    Register,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Register(usize),
+    Value(i32),
+}
+
+struct OpcodeInfo {
+    opcode: Opcode,
+    mnemonic: &'static str,
+    cycles: u32,
+    operands: &'static [OperandKind],
+}
+
+/// Every instruction the CPU understands. An instruction takes effect after its last cycle.
+const INSTRUCTION_SET: [OpcodeInfo; 5] = [
+    // noop
+    OpcodeInfo { opcode: Opcode::Noop, mnemonic: "noop", cycles: 1, operands: &[] },
+    // addx V: x += V
+    OpcodeInfo { opcode: Opcode::AddX, mnemonic: "addx", cycles: 2, operands: &[OperandKind::Value] },
+    // add R V: R += V
+    OpcodeInfo { opcode: Opcode::Add, mnemonic: "add", cycles: 2, operands: &[OperandKind::Register, OperandKind::Value] },
+    // set R V: R = V
+    OpcodeInfo { opcode: Opcode::Set, mnemonic: "set", cycles: 1, operands: &[OperandKind::Register, OperandKind::Value] },
+    // mov R S: R = S
+    OpcodeInfo { opcode: Opcode::Mov, mnemonic: "mov", cycles: 1, operands: &[OperandKind::Register, OperandKind::Register] },
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct Instruction {
+    opcode: Opcode,
+    operands: Vec<Operand>,
+}
+
+impl Instruction {
+    fn info(&self) -> &'static OpcodeInfo {
+        INSTRUCTION_SET.iter().find(|i| i.opcode == self.opcode).unwrap()
+    }
+
+    fn cycles(&self) -> u32 {
+        self.info().cycles
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.info().mnemonic)?;
+        for operand in self.operands.iter() {
+            match operand {
+                Operand::Register(r) => write!(f, " {}", REGISTERS[*r])?,
+                Operand::Value(v) => write!(f, " {}", v)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnknownOpcode(String),
+    MissingOperand(String),
+    TooManyOperands(String),
+    InvalidRegister(String),
+    InvalidValue(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownOpcode(s) => write!(f, "unknown opcode {}", s),
+            ParseError::MissingOperand(s) => write!(f, "missing operand for {}", s),
+            ParseError::TooManyOperands(s) => write!(f, "too many operands for {}", s),
+            ParseError::InvalidRegister(s) => write!(f, "invalid register {}", s),
+            ParseError::InvalidValue(s) => write!(f, "invalid value {}", s),
+        }
+    }
+}
+
+/// Screen size and sprite width, in pixels. The sprite is at least 1 pixel wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Geometry {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+}
+
+impl Geometry {
+    /// Parses `WIDTHxHEIGHT`.
+    fn parse(s: &str, sprite_width: usize) -> Option<Geometry> {
+        let (width, height) = s.split_once('x')?;
+        let geometry = Geometry { width: width.parse().ok()?, height: height.parse().ok()?, sprite_width };
+        if geometry.width == 0 || geometry.height == 0 || geometry.sprite_width == 0 {
+            return None;
+        }
+        Some(geometry)
+    }
+
+    /// Whether the sprite centred on column `sprite` covers `column`. Sprites of an even width
+    /// reach one column further right than left.
+    fn covers(&self, column: i32, sprite: i32) -> bool {
+        let width = self.sprite_width as i32;
+        column >= sprite - (width - 1) / 2 && column <= sprite + width / 2
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Geometry {
+        Geometry { width: 40, height: 6, sprite_width: 3 }
+    }
 }
 
 struct Crt {
-    buf: [char; 240],
+    geometry: Geometry,
+    buf: Vec<char>,
 }
 
 impl Crt {
-    pub fn new() -> Crt {
-        Crt { buf: [' '; 240] }
+    pub fn new(geometry: Geometry) -> Crt {
+        Crt { geometry, buf: vec![' '; geometry.width * geometry.height] }
     }
 
     pub fn display(&self) {
-        for i in 0..240 {
-            if i % 40 == 0 {
+        for (i, pixel) in self.buf.iter().enumerate() {
+            if i % self.geometry.width == 0 {
                 println!();
             }
-            print!("{}", self.buf[i]);
+            print!("{}", pixel);
         }
         println!();
     }
 
+    /// The letters drawn on the screen.
+    pub fn read(&self) -> Result<String, Vec<UnknownGlyph>> {
+        ocr::read(&self.buf, self.geometry.width)
+    }
+
+    /// Whether the sprite centred on column `sprite` covers `column`.
+    pub fn covers(&self, column: i32, sprite: i32) -> bool {
+        self.geometry.covers(column, sprite)
+    }
+
+    /// Position in `buf` drawn during a cycle, starting over at the top once the screen is full.
+    pub fn position(&self, cycle: i32) -> usize {
+        (cycle - 1) as usize % self.buf.len()
+    }
+
     pub fn set_pixel(&mut self, cycle: i32, sprite: i32) {
-        if ((cycle) % 40).abs_diff(sprite + 1) <= 1 {
-            self.buf[(cycle - 1) as usize] = '#';
-        } else {
-            self.buf[(cycle - 1) as usize] = '.';
+        let position = self.position(cycle);
+        let column = (position % self.geometry.width) as i32;
+        self.buf[position] = if self.covers(column, sprite) { '#' } else { '.' };
+    }
+
+    /// The part of the row being drawn that comes before `cycle`.
+    pub fn scanline(&self, cycle: i32) -> &[char] {
+        let position = self.position(cycle);
+        &self.buf[position - position % self.geometry.width..position]
+    }
+}
+
+/// The cycles at which the signal strength is sampled.
+#[derive(Debug, PartialEq)]
+enum Sampling {
+    Periodic { first: i32, interval: i32 },
+    Cycles(Vec<i32>),
+}
+
+impl Sampling {
+    /// Parses `FIRST/INTERVAL` or a comma separated list of cycles. The interval must be positive.
+    fn parse(s: &str) -> Option<Sampling> {
+        if let Some((first, interval)) = s.split_once('/') {
+            let interval = interval.parse().ok().filter(|i| *i > 0)?;
+            return Some(Sampling::Periodic { first: first.parse().ok()?, interval });
+        }
+        s.split(',').map(|c| c.parse::<i32>().ok()).collect::<Option<Vec<i32>>>().map(Sampling::Cycles)
+    }
+
+    fn includes(&self, cycle: i32) -> bool {
+        match self {
+            Sampling::Periodic { first, interval } => cycle >= *first && (cycle - first) % interval == 0,
+            Sampling::Cycles(cycles) => cycles.contains(&cycle),
+        }
+    }
+}
+
+impl Default for Sampling {
+    fn default() -> Sampling {
+        Sampling::Periodic { first: 20, interval: 40 }
+    }
+}
+
+/// The state of the CPU during one cycle.
+#[derive(Debug, Clone, PartialEq)]
+struct TraceEntry {
+    cycle: i32,
+    pc: usize,
+    instruction: Instruction,
+    registers: [i32; 4],
+    pixel: char,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>4} {:>4}  {:<12}", self.cycle, self.pc, self.instruction.to_string())?;
+        for (name, value) in REGISTERS.iter().zip(self.registers.iter()) {
+            write!(f, " {}={:<4}", name, value)?;
         }
+        write!(f, " {}", self.pixel)
+    }
+}
+
+fn trace_csv(trace: &[TraceEntry]) -> String {
+    let mut csv = format!("cycle,pc,instruction,{},pixel\n", REGISTERS.join(","));
+    for t in trace {
+        let registers: Vec<String> = t.registers.iter().map(|r| r.to_string()).collect();
+        csv.push_str(&format!("{},{},{},{},{}\n", t.cycle, t.pc, t.instruction, registers.join(","), t.pixel));
     }
+    csv
 }
 
 struct Cpu {
-    x: i32,
+    registers: [i32; 4],
     cycle: i32,
+    /// Index of the instruction being executed
+    pc: usize,
+    /// Cycles already spent on the current instruction
+    elapsed: u32,
     pub crt: Crt,
+    pub sampling: Sampling,
     pub total_strength: i32,
+    /// (cycle, x, strength) for every sample taken
+    pub signals: Vec<(i32, i32, i32)>,
+    pub tracing: bool,
+    pub trace: Vec<TraceEntry>,
 }
 
 impl Cpu {
-    pub fn new() -> Cpu {
-        Cpu { crt: Crt::new(), x: 1, cycle: 1, total_strength: 0 }
+    pub fn new(geometry: Geometry) -> Cpu {
+        let mut registers = [0; 4];
+        registers[X] = 1;
+        Cpu {
+            crt: Crt::new(geometry),
+            registers,
+            cycle: 1,
+            pc: 0,
+            elapsed: 0,
+            sampling: Sampling::default(),
+            total_strength: 0,
+            signals: Vec::new(),
+            tracing: false,
+            trace: Vec::new(),
+        }
     }
 
-    fn execute(&mut self, i: Instruction) {
+    /// Runs one cycle of `program`, returns false once the program has finished.
+    pub fn tick(&mut self, program: &[Instruction]) -> bool {
+        let Some(instruction) = program.get(self.pc) else {
+            return false;
+        };
+        let (cycle, registers) = (self.cycle, self.registers);
         self.next_cycle();
-        match i {
-            AddX(v) => {
-                self.next_cycle();
-                self.x += v;
-            }
-            Noop => {}
+        if self.tracing {
+            self.trace.push(TraceEntry {
+                cycle,
+                pc: self.pc,
+                instruction: instruction.clone(),
+                registers,
+                pixel: self.crt.buf[self.crt.position(cycle)],
+            });
+        }
+
+        self.elapsed += 1;
+        if self.elapsed == instruction.cycles() {
+            self.apply(instruction);
+            self.elapsed = 0;
+            self.pc += 1;
+        }
+        true
+    }
+
+    pub fn run(&mut self, program: &[Instruction]) {
+        while self.tick(program) {}
+    }
+
+    fn apply(&mut self, i: &Instruction) {
+        match (i.opcode, i.operands.as_slice()) {
+            (Opcode::Noop, []) => {}
+            (Opcode::AddX, [Operand::Value(v)]) => { self.registers[X] += v; }
+            (Opcode::Add, [Operand::Register(r), Operand::Value(v)]) => { self.registers[*r] += v; }
+            (Opcode::Set, [Operand::Register(r), Operand::Value(v)]) => { self.registers[*r] = *v; }
+            (Opcode::Mov, [Operand::Register(r), Operand::Register(s)]) => { self.registers[*r] = self.registers[*s]; }
+            _ => { panic!("Malformed instruction {:?}", i); }
         }
     }
 
     fn next_cycle(&mut self) {
-        self.crt.set_pixel(self.cycle, self.x);
-        if self.cycle == 20 || (self.cycle - 20) % 40 == 0 {
+        self.crt.set_pixel(self.cycle, self.registers[X]);
+        if self.sampling.includes(self.cycle) {
             self.emit_signal_strength();
         }
         self.cycle += 1;
     }
 
     fn emit_signal_strength(&mut self) {
-        self.total_strength += self.cycle * self.x;
-        println!("Strength @ {} = {}: {}", self.cycle, self.x, self.cycle * self.x);
+        let strength = self.cycle * self.registers[X];
+        self.total_strength += strength;
+        self.signals.push((self.cycle, self.registers[X], strength));
     }
 }
 
@@ -80,39 +344,272 @@ fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader
     Ok(io::BufReader::new(file).lines())
 }
 
-fn parse_string(i: &str) -> Instruction {
-    let mut s = i.split(" ");
-    let instruction = s.next().unwrap();
-    if instruction == "addx" {
-        return AddX(s.next().unwrap().parse::<i32>().unwrap());
+fn parse_string(i: &str) -> Result<Instruction, ParseError> {
+    let mut s = i.split_whitespace();
+    let mnemonic = s.next().unwrap_or("");
+    let info = INSTRUCTION_SET.iter()
+        .find(|info| info.mnemonic == mnemonic)
+        .ok_or_else(|| ParseError::UnknownOpcode(mnemonic.to_string()))?;
+
+    let mut operands = Vec::new();
+    for kind in info.operands {
+        let token = s.next().ok_or_else(|| ParseError::MissingOperand(mnemonic.to_string()))?;
+        operands.push(match kind {
+            OperandKind::Register => Operand::Register(
+                REGISTERS.iter().position(|r| *r == token).ok_or_else(|| ParseError::InvalidRegister(token.to_string()))?
+            ),
+            OperandKind::Value => Operand::Value(
+                token.parse::<i32>().map_err(|_| ParseError::InvalidValue(token.to_string()))?
+            ),
+        });
     }
-    Noop
+    if s.next().is_some() {
+        return Err(ParseError::TooManyOperands(mnemonic.to_string()));
+    }
+    Ok(Instruction { opcode: info.opcode, operands })
 }
 
-fn solution(verbose: usize) -> i32 {
-    let mut cpu = Cpu::new();
-    if let Ok(lines) = read_lines("input.txt") {
-        for line in lines {
-            if let Ok(l) = line {
-                if verbose >= 2 { println!("{}", l); }
-                let i = parse_string(&l);
-                cpu.execute(i);
-                if verbose >= 1 { cpu.emit_signal_strength() }
-            }
+/// Parses program lines, failing with the line number of the first bad instruction.
+///
+/// Blank lines, `;` comments and lines holding only a `label:` are skipped, so the output of
+/// `--disassemble` reads back as the program it came from.
+fn parse_lines<I: Iterator<Item=String>>(lines: I, verbose: usize) -> Result<Vec<Instruction>, (usize, ParseError)> {
+    let mut program = Vec::new();
+    for (n, l) in lines.enumerate() {
+        if verbose >= 2 { println!("{}", l); }
+        let l = l.split(';').next().unwrap_or("").trim();
+        if l.is_empty() || l.strip_suffix(':').is_some_and(|label| !label.contains(char::is_whitespace)) {
+            continue;
         }
+        program.push(parse_string(l).map_err(|e| (n + 1, e))?);
+    }
+    Ok(program)
+}
+
+/// Parses a program file, failing with the line number of the first bad instruction.
+fn parse_file(file: &str, verbose: usize) -> Result<Vec<Instruction>, (usize, ParseError)> {
+    match read_lines(file) {
+        Ok(lines) => parse_lines(lines.map_while(Result::ok), verbose),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn execute(program: &[Instruction], sampling: Sampling, geometry: Geometry, verbose: usize) -> Cpu {
+    let mut cpu = Cpu::new(geometry);
+    cpu.sampling = sampling;
+    cpu.tracing = verbose >= 1;
+    cpu.run(program);
+    cpu
+}
+
+fn solution(file: &str, sampling: Sampling, geometry: Geometry, verbose: usize) -> Result<Cpu, (usize, ParseError)> {
+    let program = parse_file(file, verbose)?;
+    Ok(execute(&program, sampling, geometry, verbose))
+}
+
+/// Reads a program file, or assembles it with `asm`.
+fn load(file: &str, asm: bool, geometry: Geometry, verbose: usize) -> Result<Program, String> {
+    if asm {
+        let source = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        assemble(&source, geometry).map_err(|(line, e)| format!("{}:{}: {}", file, line, e))
+    } else {
+        parse_file(file, verbose)
+            .map(|instructions| Program { instructions, labels: BTreeMap::new() })
+            .map_err(|(line, e)| format!("{}:{}: {}", file, line, e))
     }
-    cpu.crt.display();
-    cpu.total_strength
 }
 
 fn main() {
     let mut verbose = 0;
     let args: Vec<String> = env::args().collect();
-    if args.iter().find(|&arg| arg == "-v").is_some() {
+    if args.iter().any(|arg| arg == "-v") {
         verbose = 1;
     }
-    if args.iter().find(|&arg| arg == "-vv").is_some() {
+    if args.iter().any(|arg| arg == "-vv") {
         verbose = 2;
     }
-    println!("Solution {}", solution(verbose));
+    let option = |name: &str| args.iter().find_map(|arg| arg.strip_prefix(name));
+    let file = option("--file=").unwrap_or("input.txt");
+    let sampling = option("--sample=")
+        .map(|s| Sampling::parse(s).expect("Sampling is FIRST/INTERVAL or a list of cycles"))
+        .unwrap_or_default();
+    let trace_file = option("--trace-csv=");
+    let sprite_width = option("--sprite=")
+        .map(|s| s.parse::<usize>().ok().filter(|w| *w > 0).expect("Sprite width is a positive number"))
+        .unwrap_or(Geometry::default().sprite_width);
+    let geometry = option("--crt=")
+        .map(|s| Geometry::parse(s, sprite_width).expect("CRT size is WIDTHxHEIGHT"))
+        .unwrap_or(Geometry { sprite_width, ..Geometry::default() });
+
+    let asm = args.iter().any(|arg| arg == "--asm");
+    let debug = args.iter().any(|arg| arg == "--debug");
+    let listing = args.iter().any(|arg| arg == "--disassemble");
+    let trace_verbose = if trace_file.is_some() { verbose.max(1) } else { verbose };
+
+    let mut cpu = if asm || debug || listing {
+        let program = match load(file, asm, geometry, verbose) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if debug {
+            Debugger::new(program.instructions, geometry).run();
+            return;
+        }
+        if listing {
+            print!("{}", disassemble(&program));
+            return;
+        }
+        execute(&program.instructions, sampling, geometry, trace_verbose)
+    } else {
+        match solution(file, sampling, geometry, trace_verbose) {
+            Ok(cpu) => cpu,
+            Err((line, e)) => {
+                println!("{}:{}: {}", file, line, e);
+                return;
+            }
+        }
+    };
+    if verbose >= 1 {
+        for t in cpu.trace.iter() {
+            println!("{}", t);
+        }
+        for (cycle, x, strength) in cpu.signals.iter() {
+            println!("Strength @ {} = {}: {}", cycle, x, strength);
+        }
+    }
+    if let Some(trace_file) = trace_file {
+        fs::write(trace_file, trace_csv(&cpu.trace)).unwrap();
+        cpu.trace.clear();
+    }
+    cpu.crt.display();
+    match cpu.crt.read() {
+        Ok(text) => println!("Letters {}", text),
+        Err(unknown) => unknown.iter().for_each(|g| print!("{}", g)),
+    }
+    println!("Solution {}", cpu.total_strength);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Cpu, Crt, Geometry, Instruction, Opcode, Operand, parse_string, ParseError, Sampling, solution, trace_csv};
+
+    #[test]
+    fn test_parse_string() {
+        assert_eq!(parse_string("noop"), Ok(Instruction { opcode: Opcode::Noop, operands: vec![] }));
+        assert_eq!(parse_string("addx -11"), Ok(Instruction { opcode: Opcode::AddX, operands: vec![Operand::Value(-11)] }));
+        assert_eq!(parse_string("add y 3"), Ok(Instruction { opcode: Opcode::Add, operands: vec![Operand::Register(1), Operand::Value(3)] }));
+        assert_eq!(parse_string("mov x w"), Ok(Instruction { opcode: Opcode::Mov, operands: vec![Operand::Register(0), Operand::Register(3)] }));
+        assert_eq!(parse_string("addy 3"), Err(ParseError::UnknownOpcode(String::from("addy"))));
+        assert_eq!(parse_string("addx"), Err(ParseError::MissingOperand(String::from("addx"))));
+        assert_eq!(parse_string("noop 1"), Err(ParseError::TooManyOperands(String::from("noop"))));
+        assert_eq!(parse_string("set q 1"), Err(ParseError::InvalidRegister(String::from("q"))));
+        assert_eq!(parse_string("addx one"), Err(ParseError::InvalidValue(String::from("one"))));
+        assert_eq!(parse_string("add y 3").unwrap().to_string(), "add y 3");
+    }
+
+    #[test]
+    fn test_registers() {
+        let program: Vec<Instruction> = ["set y 5", "add y -2", "mov x y", "noop"]
+            .iter()
+            .map(|l| parse_string(l).unwrap())
+            .collect();
+        let mut cpu = Cpu::new(Geometry::default());
+        cpu.tracing = true;
+        cpu.run(&program);
+        assert_eq!(cpu.registers, [3, 3, 0, 0]);
+        assert_eq!(cpu.cycle, 6);
+        assert_eq!(cpu.trace.iter().map(|t| t.pc).collect::<Vec<usize>>(), vec![0, 1, 1, 2, 3]);
+        assert_eq!(cpu.trace[4].registers, [3, 3, 0, 0]);
+    }
+
+    #[test]
+    fn test_sampling() {
+        assert_eq!(Sampling::parse("20/40"), Some(Sampling::Periodic { first: 20, interval: 40 }));
+        assert_eq!(Sampling::parse("1,2,3"), Some(Sampling::Cycles(vec![1, 2, 3])));
+        assert_eq!(Sampling::parse("x"), None);
+        let cpu = solution("test.txt", Sampling::Cycles(vec![20, 60]), Geometry::default(), 0).unwrap();
+        assert_eq!(cpu.signals, vec![(20, 21, 420), (60, 19, 1140)]);
+    }
+
+    #[test]
+    fn test_sampling_interval() {
+        assert_eq!(Sampling::parse("20/0"), None);
+        assert_eq!(Sampling::parse("20/-40"), None);
+        assert_eq!(Sampling::parse("-20/1"), Some(Sampling::Periodic { first: -20, interval: 1 }));
+    }
+
+    #[test]
+    fn test_trace_csv() {
+        let cpu = solution("test.txt", Sampling::default(), Geometry::default(), 1).unwrap();
+        assert_eq!(cpu.trace.len(), 240);
+        let csv = trace_csv(&cpu.trace);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("cycle,pc,instruction,x,y,z,w,pixel"));
+        assert_eq!(lines.next(), Some("1,0,addx 15,1,0,0,0,#"));
+        assert_eq!(lines.next(), Some("2,0,addx 15,1,0,0,0,#"));
+        assert_eq!(lines.next(), Some("3,1,addx -11,16,0,0,0,."));
+    }
+
+    #[test]
+    fn test_geometry() {
+        assert_eq!(Geometry::parse("20x12", 1), Some(Geometry { width: 20, height: 12, sprite_width: 1 }));
+        assert_eq!(Geometry::parse("0x12", 1), None);
+        assert_eq!(Geometry::parse("20x12", 0), None);
+        let mut crt = Crt::new(Geometry { width: 5, height: 2, sprite_width: 3 });
+        for cycle in 1..=10 {
+            crt.set_pixel(cycle, 0);
+        }
+        assert_eq!(crt.buf.iter().collect::<String>(), "##...##...");
+        // Past the bottom the beam starts over at the top
+        crt.set_pixel(11, 4);
+        assert_eq!(crt.buf[0], '.');
+        assert_eq!(crt.scanline(9), ['#', '#', '.']);
+
+        let wide = Crt::new(Geometry { width: 40, height: 6, sprite_width: 4 });
+        assert_eq!((3..9).filter(|c| wide.covers(*c, 5)).collect::<Vec<i32>>(), vec![4, 5, 6, 7]);
+
+        let cpu = solution("input.txt", Sampling::default(), Geometry { width: 48, height: 5, sprite_width: 1 }, 0).unwrap();
+        assert_eq!(cpu.total_strength, 15020);
+        assert_eq!(cpu.crt.buf.len(), 240);
+        assert_eq!(cpu.crt.buf[..7].iter().collect::<String>(), ".##....");
+    }
+
+    #[test]
+    fn test_last_column() {
+        // The last column is lit by a sprite next to it, not by one at the start of the row
+        let mut crt = Crt::new(Geometry::default());
+        crt.set_pixel(40, 0);
+        crt.set_pixel(80, 39);
+        crt.set_pixel(120, 40);
+        crt.set_pixel(160, 41);
+        assert_eq!([crt.buf[39], crt.buf[79], crt.buf[119], crt.buf[159]], ['.', '#', '#', '.']);
+    }
+
+    #[test]
+    fn test_solution() {
+        assert_eq!(solution("test.txt", Sampling::default(), Geometry::default(), 0).unwrap().total_strength, 13140);
+        let cpu = solution("input.txt", Sampling::default(), Geometry::default(), 0).unwrap();
+        assert_eq!(cpu.total_strength, 15020);
+        assert_eq!(cpu.crt.read(), Ok(String::from("EFUGLPAP")));
+        // The last column wraps like the others and stays dark between the letters
+        assert!(cpu.crt.buf.iter().skip(39).step_by(40).all(|c| *c == '.'));
+    }
+
+    #[test]
+    fn test_example_screen() {
+        // The image of the puzzle example. The baseline left the end of the fifth row dark
+        let cpu = solution("test.txt", Sampling::default(), Geometry::default(), 0).unwrap();
+        let rows: Vec<String> = cpu.crt.buf.chunks(40).map(|row| row.iter().collect()).collect();
+        assert_eq!(rows, vec![
+            "##..##..##..##..##..##..##..##..##..##..",
+            "###...###...###...###...###...###...###.",
+            "####....####....####....####....####....",
+            "#####.....#####.....#####.....#####.....",
+            "######......######......######......####",
+            "#######.......#######.......#######.....",
+        ]);
+    }
 }
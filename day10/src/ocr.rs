@@ -0,0 +1,122 @@
+use std::fmt;
+
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+/// Glyphs are drawn in cells with one blank column after each letter.
+pub const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+
+/// The letters of the puzzle font that are known to appear, rows from top to bottom.
+pub const FONT: [(char, [&str; GLYPH_HEIGHT]); 16] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+pub fn glyph(letter: char) -> Option<&'static [&'static str; GLYPH_HEIGHT]> {
+    FONT.iter().find(|(c, _)| *c == letter).map(|(_, rows)| rows)
+}
+
+/// A cell that does not match any letter of the font.
+#[derive(Debug, PartialEq)]
+pub struct UnknownGlyph {
+    /// Index of the cell, counting from the left
+    pub position: usize,
+    pub rows: Vec<String>,
+}
+
+impl fmt::Display for UnknownGlyph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "unknown glyph at position {}:", self.position)?;
+        for row in self.rows.iter() {
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the letters off a screen `width` pixels wide, lit pixels being `#`.
+///
+/// The screen is cut in cells of `CELL_WIDTH` columns and the left `GLYPH_WIDTH` columns of
+/// each are matched against the font, so whatever is drawn in the spacer column is ignored.
+/// An empty cell reads as a space, except at the end, where empty cells are left out.
+pub fn read(buf: &[char], width: usize) -> Result<String, Vec<UnknownGlyph>> {
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+    for position in 0..width / CELL_WIDTH {
+        let rows: Vec<String> = (0..GLYPH_HEIGHT)
+            .map(|y| {
+                let start = y * width + position * CELL_WIDTH;
+                buf.get(start..start + GLYPH_WIDTH)
+                    .map_or(String::from("...."), |row| row.iter().map(|c| if *c == '#' { '#' } else { '.' }).collect())
+            })
+            .collect();
+        if rows.iter().all(|row| !row.contains('#')) {
+            text.push(' ');
+            continue;
+        }
+        match FONT.iter().find(|(_, glyph)| glyph.iter().zip(rows.iter()).all(|(g, r)| g == r)) {
+            Some((letter, _)) => text.push(*letter),
+            None => unknown.push(UnknownGlyph { position, rows }),
+        }
+    }
+    if unknown.is_empty() {
+        Ok(text.trim_end().to_string())
+    } else {
+        Err(unknown)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ocr::{read, UnknownGlyph};
+
+    fn screen(rows: &[&str]) -> Vec<char> {
+        rows.iter().flat_map(|r| r.chars()).collect()
+    }
+
+    #[test]
+    fn test_read() {
+        let buf = screen(&[
+            "#..#.####......###..",
+            "#..#.#.........#..#.",
+            "####.###.......#..#.",
+            "#..#.#.........###..",
+            "#..#.#.........#....",
+            "#..#.####......#....",
+        ]);
+        assert_eq!(read(&buf, 20), Ok(String::from("HE P")));
+        // Empty cells after the last letter
+        let buf = screen(&[
+            "#..#.....",
+            "#..#.....",
+            "####.....",
+            "#..#.....",
+            "#..#.....",
+            "#..#.....",
+        ]);
+        assert_eq!(read(&buf, 9), Ok(String::from("H")));
+        assert_eq!(read(&vec!['.'; 60], 10), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_unknown_glyph() {
+        let buf = screen(&["#...#", ".#...", "..#..", "...#.", "....#", "....."]);
+        assert_eq!(read(&buf, 5), Err(vec![UnknownGlyph {
+            position: 0,
+            rows: ["#...", ".#..", "..#.", "...#", "....", "...."].iter().map(|r| r.to_string()).collect(),
+        }]));
+    }
+}
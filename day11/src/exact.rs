@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use num_bigint::BigUint;
+
+use crate::{common_modulo, Monkey, round, RunError};
+
+/// A throw, as (from monkey, to monkey).
+pub type Throw = (usize, usize);
+
+/// Runs the rounds on exact worry levels, without reducing them by the common modulo.
+///
+/// Returns how many items every monkey inspected and every throw in order. Items are
+/// inspected in the same order as `Monkey::inspect_next_item` does, and an item a monkey
+/// keeps throwing to itself is refused like `round` does.
+///
+/// Exact worry levels of an item thrown back can grow without ever repeating, so with a factor
+/// of 1 they are compared modulo the common modulo, which decides every later throw. Other
+/// factors do not carry over to reduced worry levels, so there only an exact repeat is caught.
+pub fn simulate_exact(monkeys: &[Monkey], iterations: i32, worriedness_factor: u64) -> Result<(Vec<u64>, Vec<Throw>), RunError> {
+    let mut items: Vec<Vec<BigUint>> = monkeys.iter()
+        .map(|m| m.items.iter().map(|i| BigUint::from(*i)).collect())
+        .collect();
+    let mut inspections = vec![0; monkeys.len()];
+    let mut throws = Vec::new();
+    let zero = BigUint::from(0u32);
+    let reduced = if worriedness_factor == 1 { common_modulo(monkeys).ok() } else { None };
+    for _ in 0..iterations {
+        for (i, m) in monkeys.iter().enumerate() {
+            let mut kept = HashSet::new();
+            while let Some(item) = items[i].pop() {
+                inspections[i] += 1;
+                let n = m.operation.eval_big(&item).map_err(|e| RunError::Eval(i, e))? / worriedness_factor;
+                let target = if &n % m.test == zero { m.true_monkey } else { m.false_monkey };
+                if target != i {
+                    kept.clear();
+                } else if !kept.insert(reduced.map_or_else(|| n.clone(), |m| &n % m)) {
+                    return Err(RunError::Endless(i));
+                }
+                throws.push((i, target));
+                items[target].push(n);
+            }
+        }
+    }
+    Ok((inspections, throws))
+}
+
+/// The first throw on which both simulations part ways.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub index: usize,
+    pub modular: Option<Throw>,
+    pub exact: Option<Throw>,
+}
+
+/// Compares the throws of both simulations for these monkeys, starting items and number of
+/// rounds, returning the number of throws compared. Fails if either simulation cannot run.
+///
+/// Agreement on one run is a sanity check of the modular simulation, not a proof that it is
+/// exact for other notes or more rounds.
+pub fn check_modular(monkeys: &mut [Monkey], iterations: i32, worriedness_factor: u64) -> Result<Result<usize, Mismatch>, RunError> {
+    let (_, exact) = simulate_exact(monkeys, iterations, worriedness_factor)?;
+    let common_modulo = common_modulo(monkeys)?;
+    let mut modular: Vec<Throw> = Vec::new();
+    for _ in 0..iterations {
+        modular.extend(round(monkeys, worriedness_factor, common_modulo)?);
+    }
+    Ok(match (0..modular.len().max(exact.len())).find(|i| modular.get(*i) != exact.get(*i)) {
+        Some(index) => Err(Mismatch { index, modular: modular.get(index).copied(), exact: exact.get(index).copied() }),
+        None => Ok(exact.len()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_file;
+    use crate::exact::{check_modular, simulate_exact};
+
+    #[test]
+    fn test_simulate_exact() {
+        let monkeys = parse_file("test.txt").unwrap();
+        let (inspections, _) = simulate_exact(&monkeys, 20, 3).unwrap();
+        assert_eq!(inspections, vec![101, 95, 7, 105]);
+        let (inspections, _) = simulate_exact(&monkeys, 20, 1).unwrap();
+        assert_eq!(inspections, vec![99, 97, 8, 103]);
+    }
+
+    #[test]
+    fn test_check_modular() {
+        for file in ["test.txt", "input.txt"] {
+            assert!(check_modular(&mut parse_file(file).unwrap(), 20, 3).unwrap().is_ok());
+            assert!(check_modular(&mut parse_file(file).unwrap(), 20, 1).unwrap().is_ok());
+        }
+    }
+}
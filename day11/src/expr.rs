@@ -0,0 +1,294 @@
+use std::fmt;
+
+use num_bigint::BigUint;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn symbol(&self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
+
+/// Why an expression could not be evaluated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvalError {
+    Overflow,
+    /// A subtraction went below zero
+    Underflow,
+    DivisionByZero,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Overflow => write!(f, "worry level overflows"),
+            EvalError::Underflow => write!(f, "worry level goes below zero"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+/// The right hand side of a monkey operation, `new = <expression>`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Old,
+    Value(u64),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Old,
+    Value(u64),
+    Op(Op),
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => { chars.next(); }
+            '+' => { chars.next(); tokens.push(Token::Op(Op::Add)); }
+            '-' => { chars.next(); tokens.push(Token::Op(Op::Sub)); }
+            '*' => { chars.next(); tokens.push(Token::Op(Op::Mul)); }
+            '/' => { chars.next(); tokens.push(Token::Op(Op::Div)); }
+            '(' => { chars.next(); tokens.push(Token::Open); }
+            ')' => { chars.next(); tokens.push(Token::Close); }
+            '0'..='9' => {
+                let mut n = String::new();
+                while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    n.push(d);
+                }
+                tokens.push(Token::Value(n.parse().ok()?));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(l) = chars.next_if(char::is_ascii_alphabetic) {
+                    word.push(l);
+                }
+                if word != "old" {
+                    return None;
+                }
+                tokens.push(Token::Old);
+            }
+        }
+    }
+    Some(tokens)
+}
+
+/// Recursive descent over the tokens, one level per precedence.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, precedence: u8) -> Option<Expr> {
+        if precedence > 2 {
+            return self.operand();
+        }
+        let mut left = self.binary(precedence + 1)?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos) {
+            if op.precedence() != precedence {
+                break;
+            }
+            let op = *op;
+            self.pos += 1;
+            let right = self.binary(precedence + 1)?;
+            if op == Op::Div && right == Expr::Value(0) {
+                return None;
+            }
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn operand(&mut self) -> Option<Expr> {
+        match self.next()? {
+            Token::Old => Some(Expr::Old),
+            Token::Value(v) => Some(Expr::Value(v)),
+            Token::Open => {
+                let e = self.binary(1)?;
+                (self.next()? == Token::Close).then_some(e)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Expr {
+    /// Parses an expression over `old` and integers with `+ - * /` and parentheses.
+    /// Operators associate to the left and `*` and `/` bind tighter than `+` and `-`.
+    /// Dividing by a literal 0 is rejected.
+    pub fn parse(s: &str) -> Option<Expr> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0 };
+        let e = parser.binary(1)?;
+        (parser.pos == parser.tokens.len()).then_some(e)
+    }
+
+    /// Evaluates the expression for a worry level. Division rounds down.
+    pub fn eval(&self, old: u64) -> Result<u64, EvalError> {
+        match self {
+            Expr::Old => Ok(old),
+            Expr::Value(v) => Ok(*v),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(old)?, b.eval(old)?);
+                match op {
+                    Op::Add => a.checked_add(b).ok_or(EvalError::Overflow),
+                    Op::Sub => a.checked_sub(b).ok_or(EvalError::Underflow),
+                    Op::Mul => a.checked_mul(b).ok_or(EvalError::Overflow),
+                    Op::Div => a.checked_div(b).ok_or(EvalError::DivisionByZero),
+                }
+            }
+        }
+    }
+
+    /// Whether the expression only uses `+` and `*`, so that its result modulo the common
+    /// modulo of the monkeys only depends on `old` modulo the same.
+    pub fn is_modular(&self) -> bool {
+        match self {
+            Expr::Old | Expr::Value(_) => true,
+            Expr::Binary(op, a, b) => matches!(op, Op::Add | Op::Mul) && a.is_modular() && b.is_modular(),
+        }
+    }
+
+    /// Like `eval`, on exact worry levels of any size, which cannot overflow.
+    pub fn eval_big(&self, old: &BigUint) -> Result<BigUint, EvalError> {
+        match self {
+            Expr::Old => Ok(old.clone()),
+            Expr::Value(v) => Ok(BigUint::from(*v)),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval_big(old)?, b.eval_big(old)?);
+                match op {
+                    Op::Add => Ok(a + b),
+                    Op::Sub if a < b => Err(EvalError::Underflow),
+                    Op::Sub => Ok(a - b),
+                    Op::Mul => Ok(a * b),
+                    Op::Div if b == BigUint::ZERO => Err(EvalError::DivisionByZero),
+                    Op::Div => Ok(a / b),
+                }
+            }
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut fmt::Formatter, parent: Op, right: bool) -> fmt::Result {
+        // Parentheses are needed for a looser operator, or an equal one on the right unless
+        // both are the same associative operator
+        let parenthesize = match self {
+            Expr::Binary(op, _, _) => op.precedence() < parent.precedence()
+                || (right && op.precedence() == parent.precedence() && !(op == &parent && matches!(op, Op::Add | Op::Mul))),
+            _ => false,
+        };
+        if parenthesize {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Value(v) => write!(f, "{}", v),
+            Expr::Binary(op, a, b) => {
+                a.fmt_operand(f, *op, false)?;
+                write!(f, " {} ", op.symbol())?;
+                b.fmt_operand(f, *op, true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num_bigint::BigUint;
+
+    use crate::expr::{EvalError, Expr};
+
+    #[test]
+    fn test_eval() {
+        let eval = |s: &str, old: u64| Expr::parse(s).unwrap().eval(old).unwrap();
+        assert_eq!(eval("old * 19", 79), 1501);
+        assert_eq!(eval("old - 3", 10), 7);
+        assert_eq!(eval("old * old + old", 4), 20);
+        assert_eq!(eval("(old + 2) * 5", 4), 30);
+        assert_eq!(eval("old - 2 - 3", 10), 5);
+        assert_eq!(eval("100 / old / 2", 5), 10);
+        assert_eq!(eval("((old))", 8), 8);
+        let big = Expr::parse("old * old - 1").unwrap().eval_big(&BigUint::from(u64::MAX)).unwrap();
+        assert_eq!(big.to_string(), "340282366920938463426481119284349108224");
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let eval = |s: &str, old: u64| Expr::parse(s).unwrap().eval(old);
+        assert_eq!(eval("old - 3", 2), Err(EvalError::Underflow));
+        assert_eq!(eval("old * old", u64::MAX), Err(EvalError::Overflow));
+        assert_eq!(eval("old + 1", u64::MAX), Err(EvalError::Overflow));
+        assert_eq!(eval("3 / old", 0), Err(EvalError::DivisionByZero));
+        assert_eq!(eval("old / (old - 4)", 4), Err(EvalError::DivisionByZero));
+        let eval_big = |s: &str, old: u64| Expr::parse(s).unwrap().eval_big(&BigUint::from(old));
+        assert_eq!(eval_big("old - 3", 2), Err(EvalError::Underflow));
+        assert_eq!(eval_big("old / (old - 4)", 4), Err(EvalError::DivisionByZero));
+        assert_eq!(eval_big("old * old", u64::MAX).map(|n| n > BigUint::from(u64::MAX)), Ok(true));
+        assert!(Expr::parse("old * (old + 2)").unwrap().is_modular());
+        assert!(!Expr::parse("old * 2 - 1").unwrap().is_modular());
+        assert!(!Expr::parse("(old / 2) * 5").unwrap().is_modular());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Expr::parse("old+7"), Expr::parse("old + 7"));
+        for invalid in ["", "old +", "old ^ 2", "(old", "old)", "new + 1", "old old", "* 3", "old / 0", "old / (0)"] {
+            assert_eq!(Expr::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        for s in ["old * 19", "old * old + old", "(old + 2) * 5", "old - (2 - 3)", "old - 2 - 3", "old / (old * 2)", "3 + old * 2"] {
+            assert_eq!(Expr::parse(s).unwrap().to_string(), s);
+        }
+        assert_eq!(Expr::parse("(old * 2) + (3)").unwrap().to_string(), "old * 2 + 3");
+        assert_eq!(Expr::parse("old * (6 / 4)").unwrap().to_string(), "old * (6 / 4)");
+        assert_eq!(Expr::parse("old + (2 + old)").unwrap().to_string(), "old + 2 + old");
+    }
+
+    #[test]
+    fn test_print_parse() {
+        for s in ["old * (6 / 4)", "old / (6 * 4)", "old + (2 - old)", "old - (2 + old)", "old * (old / 2) * 3", "(old - 1) / (old + 1)"] {
+            let e = Expr::parse(s).unwrap();
+            assert_eq!(Expr::parse(&e.to_string()), Some(e), "{}", s);
+        }
+    }
+}
@@ -1,60 +1,29 @@
-use std::collections::BinaryHeap;
-use std::env;
+use std::collections::{BinaryHeap, HashSet};
+use std::{env, fmt, fs};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+use crate::exact::{check_modular, simulate_exact, Throw};
+use crate::expr::{EvalError, Expr};
+use crate::parser::{parse_notes, ParseError};
+use crate::report::Report;
+use crate::trajectory::{monkey_business, trajectories, trajectory_csv};
+
+mod exact;
+mod expr;
+mod parser;
+mod report;
+mod trajectory;
+
 fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>> {
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
 
-trait ThrowStrategy {
-    fn calculate_new_woriness(&self, i: u64) -> u64;
-    fn debug(&self);
-}
-
-struct MultiplayingStrategy {
-    multiplier: u64,
-}
-
-impl ThrowStrategy for MultiplayingStrategy {
-    fn calculate_new_woriness(&self, i: u64) -> u64 {
-        self.multiplier * i
-    }
-
-    fn debug(&self) {
-        println!("    Worry level is multiplied by {} to ?.", self.multiplier);
-    }
-}
-
-struct AddingStrategy {
-    addition: u64,
-}
-
-impl ThrowStrategy for AddingStrategy {
-    fn calculate_new_woriness(&self, i: u64) -> u64 {
-        self.addition + i
-    }
-    fn debug(&self) {
-        println!("    Worry level is added by {} to ?.", self.addition);
-    }
-}
-
-struct QuadraticStrategy {}
-
-impl ThrowStrategy for QuadraticStrategy {
-    fn calculate_new_woriness(&self, i: u64) -> u64 {
-        i * i
-    }
-    fn debug(&self) {
-        println!("    Worry level is squared to ?.");
-    }
-}
-
 struct Monkey {
     pub items: Vec<u64>,
-    operation: Box<dyn ThrowStrategy>,
+    operation: Expr,
     test: u64,
     true_monkey: usize,
     false_monkey: usize,
@@ -62,109 +31,100 @@ struct Monkey {
 }
 
 impl Monkey {
-    fn parse_block(buf: &Vec<String>) -> Monkey {
-        // Parse items
-        //   Starting items: 60, 84, 84, 65
-        let mut items = Vec::new();
-        let mut s = buf[1].split(" ");
-        (0..4).for_each(|_| { s.next(); });
-
-        while let Some(i) = s.next() {
-            let item = i.split(",").next().unwrap();
-            items.push(item.parse::<u64>().expect(&format!("Not integer {} in {}", i, buf[1])));
-        }
-
-        //   Operation: new = old + 7
-        s = buf[2].split(" ");
-        (0..6).for_each(|_| { s.next(); });
-        let operation: Box<dyn ThrowStrategy> = match (s.next().unwrap(), s.next().unwrap()) {
-            ("+", v) => {
-                Box::new(AddingStrategy { addition: v.parse::<u64>().expect(&format!("Not integer {}", v)) })
-            }
-            ("*", "old") => {
-                Box::new(QuadraticStrategy {})
-            }
-            ("*", v) => {
-                Box::new(MultiplayingStrategy { multiplier: v.parse::<u64>().unwrap() })
-            }
-            (o, v) => { panic!("Unknown operator {} {}", o, v); }
-        };
-
-        //   Test: divisible by 19
-        s = buf[3].split(" ");
-        (0..5).for_each(|_| { s.next(); });
-        let test = s.next().unwrap().parse::<u64>().expect(&format!("Error on line {}", buf[3]));
-
-        //     If true: throw to monkey 2
-        s = buf[4].split(" ");
-        (0..9).for_each(|_| { s.next(); });
-        let true_monkey = s.next().unwrap().parse::<usize>().expect(&format!("Error on line {}", buf[4]));
-
-        //     If false: throw to monkey 7
-        s = buf[5].split(" ");
-        (0..9).for_each(|_| { s.next(); });
-        let false_monkey = s.next().unwrap().parse::<usize>().expect(&format!("Error on line {}", buf[5]));
-
-        Monkey {
-            items,
-            operation,
-            test,
-            true_monkey,
-            false_monkey,
-            inspection_count: 0,
+    /// Where an item with worry level `i` is thrown, and its new worry level.
+    fn inspect(&self, i: u64, w: u64, common_modulo: u64) -> Result<(usize, u64), EvalError> {
+        let n = self.operation.eval(i)? / w % common_modulo;
+        if n.is_multiple_of(self.test) {
+            return Ok((self.true_monkey, n));
         }
+        Ok((self.false_monkey, n))
     }
 
-    fn inspect_next_item(&mut self, w: u64, common_modulo: u64) -> Option<(usize, u64, u64)> {
+    fn inspect_next_item(&mut self, w: u64, common_modulo: u64) -> Result<Option<(usize, u64)>, EvalError> {
         if let Some(i) = self.items.pop() {
             self.inspection_count += 1;
-            let n = self.operation.calculate_new_woriness(i) / w % common_modulo;
-            if n % self.test == 0 {
-                return Some((self.true_monkey, i, n));
-            } else {
-                return Some((self.false_monkey, i, n));
-            }
+            return self.inspect(i, w, common_modulo).map(Some);
         }
-        None
+        Ok(None)
     }
 }
 
-fn parse_file() -> Vec<Monkey> {
-    let mut monkeys = Vec::new();
-    let mut buf = Vec::new();
-    if let Ok(lines) = read_lines("input.txt") {
-        for (i, line) in lines.enumerate() {
-            if let Ok(l) = line {
-                buf.push(l);
-                if i % 7 == 6 {
-                    monkeys.push(Monkey::parse_block(&buf));
-                    buf = Vec::new();
-                }
-            }
+/// Why the monkeys could not be simulated.
+#[derive(Debug, PartialEq)]
+enum RunError {
+    /// The operation of this monkey uses `-` or `/`, which give wrong results on worry levels
+    /// reduced by the common modulo
+    NotModular(usize),
+    /// The operation of this monkey failed on a worry level
+    Eval(usize, EvalError),
+    /// This monkey keeps throwing an item to itself
+    Endless(usize),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::NotModular(n) => write!(f, "the operation of monkey {} uses - or /, which only work on exact worry levels", n),
+            RunError::Eval(n, e) => write!(f, "monkey {}: {}", n, e),
+            RunError::Endless(n) => write!(f, "monkey {} keeps throwing an item to itself", n),
         }
     }
-    monkeys
 }
 
-fn round(monkeys: &mut Vec<Monkey>, worriedness_factor: u64, common_modulo: u64, verbose: i32) {
+fn parse_file(file: &str) -> Result<Vec<Monkey>, (usize, ParseError)> {
+    let lines: Vec<String> = read_lines(file).map(|lines| lines.map_while(Result::ok).collect()).unwrap_or_default();
+    parse_notes(&lines.join("\n"))
+}
+
+/// The product of all test divisors, which keeps every test result when worry levels are reduced by it.
+///
+/// Monkeys whose operation does not carry over to reduced worry levels are refused.
+fn common_modulo(monkeys: &[Monkey]) -> Result<u64, RunError> {
+    if let Some(i) = monkeys.iter().position(|m| !m.operation.is_modular()) {
+        return Err(RunError::NotModular(i));
+    }
+    Ok(monkeys.iter().fold(1, |i, m| { i * m.test }))
+}
+
+/// Runs one round, returning every throw as (from monkey, to monkey).
+///
+/// An item a monkey throws to itself lands on top of its items and is inspected again right
+/// away. If it comes back with a worry level it already had, it would never leave.
+fn round(monkeys: &mut [Monkey], worriedness_factor: u64, common_modulo: u64) -> Result<Vec<Throw>, RunError> {
+    let mut throws = Vec::new();
     for i in 0..monkeys.len() {
-        if verbose >= 3 { println!("Monkey {}", i); }
-        while let Some((new_monkey, old_item, new_item)) = monkeys[i].inspect_next_item(worriedness_factor, common_modulo) {
-            if verbose >= 3 { println!("  Monkey inspects an item with a worry level of {}", old_item); }
-            if verbose >= 3 { println!("    Monkey gets bored with item. Worry level is divided by 3 to {}.", new_item); }
-            if verbose >= 3 { println!("    Item with worry level {} is thrown to monkey {}.", new_item, new_monkey); }
+        let mut kept = HashSet::new();
+        while let Some((new_monkey, new_item)) = monkeys[i].inspect_next_item(worriedness_factor, common_modulo)
+            .map_err(|e| RunError::Eval(i, e))? {
+            if new_monkey != i {
+                kept.clear();
+            } else if !kept.insert(new_item) {
+                return Err(RunError::Endless(i));
+            }
             monkeys[new_monkey].items.push(new_item);
+            throws.push((i, new_monkey));
         }
     }
+    Ok(throws)
+}
+
+/// Reads the monkey notes, with errors as `file:line: message`.
+fn load(file: &str) -> Result<Vec<Monkey>, String> {
+    parse_file(file).map_err(|(line, e)| format!("{}:{}: {}", file, line, e))
 }
 
-fn solution(iterations: i32, worriedness_factor: u64, verbose: i32) -> i64 {
-    let mut monkeys = parse_file();
-    let common_modulo = monkeys.iter().fold(1, |i, m| { i * m.test });
-    if verbose >= 1 { println!("Common Modulo: {}", common_modulo); }
+fn solution(file: &str, iterations: i32, worriedness_factor: u64, verbose: i32) -> Result<i64, String> {
+    let mut monkeys = load(file)?;
+    let common_modulo = common_modulo(&monkeys).map_err(|e| format!("{}: {}", file, e))?;
+    if verbose >= 1 {
+        for (i, m) in monkeys.iter().enumerate() {
+            println!("Monkey {}: new = {}", i, m.operation);
+        }
+        println!("Common Modulo: {}", common_modulo);
+    }
     for i in 0..iterations {
         if verbose >= 2 { println!("------ Round {} ------", i); }
-        round(&mut monkeys, worriedness_factor, common_modulo, verbose);
+        round(&mut monkeys, worriedness_factor, common_modulo).map_err(|e| format!("{}: {}", file, e))?;
         if verbose >= 2 {
             for m in monkeys.iter() {
                 println!("Monkey x: {}", m.items.len());
@@ -176,40 +136,155 @@ fn solution(iterations: i32, worriedness_factor: u64, verbose: i32) -> i64 {
         if verbose >= 1 { println!("Monkey {} inspected items {} times.", i, m.inspection_count); }
         h.push(m.inspection_count as i64);
     }
-    h.pop().unwrap() * h.pop().unwrap()
+    Ok(h.pop().unwrap_or(0) * h.pop().unwrap_or(0))
+}
+
+/// Like `solution`, but keeping the exact worry levels. Only practical for a few rounds.
+fn solution_exact(file: &str, iterations: i32, worriedness_factor: u64, verbose: i32) -> Result<i64, String> {
+    let monkeys = load(file)?;
+    let (inspections, _) = simulate_exact(&monkeys, iterations, worriedness_factor).map_err(|e| format!("{}: {}", file, e))?;
+    let mut h = BinaryHeap::new();
+    for (i, count) in inspections.iter().enumerate() {
+        if verbose >= 1 { println!("Monkey {} inspected items {} times.", i, count); }
+        h.push(*count as i64);
+    }
+    Ok(h.pop().unwrap_or(0) * h.pop().unwrap_or(0))
 }
 
 fn main() {
     let mut verbose = 0;
     let args: Vec<String> = env::args().collect();
-    if args.iter().find(|&arg| arg == "-v").is_some() {
+    if args.iter().any(|arg| arg == "-v") {
         verbose = 1;
     }
-    if args.iter().find(|&arg| arg == "-vv").is_some() {
+    if args.iter().any(|arg| arg == "-vv") {
         verbose = 2;
     }
-    if args.iter().find(|&arg| arg == "-vvv").is_some() {
-        verbose = 3;
+
+    let file = args.iter().find_map(|arg| arg.strip_prefix("--file=")).unwrap_or("input.txt");
+
+    if args.iter().any(|arg| arg == "--report") {
+        let rounds = args.iter()
+            .find_map(|arg| arg.strip_prefix("--rounds="))
+            .map_or(20, |r| r.parse::<usize>().expect("Rounds is a number"));
+        let worriedness_factor = args.iter()
+            .find_map(|arg| arg.strip_prefix("--factor="))
+            .map_or(3, |f| f.parse::<u64>().ok().filter(|f| *f > 0).expect("Factor is a positive number"));
+        let report = match load(file).and_then(|mut monkeys| {
+            Report::collect(&mut monkeys, rounds, worriedness_factor).map_err(|e| format!("{}: {}", file, e))
+        }) {
+            Ok(report) => report,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        print!("{}", report.summary());
+        if let Some(prefix) = args.iter().find_map(|arg| arg.strip_prefix("--csv=")) {
+            fs::write(format!("{}inspections.csv", prefix), report.inspections_csv()).unwrap();
+            fs::write(format!("{}throws.csv", prefix), report.throws_csv()).unwrap();
+            fs::write(format!("{}held.csv", prefix), report.held_csv()).unwrap();
+        }
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--cycles") {
+        let rounds = args.iter()
+            .find_map(|arg| arg.strip_prefix("--rounds="))
+            .map_or(1_000_000_000_000, |r| r.parse::<u64>().expect("Rounds is a number"));
+        let t = match load(file).and_then(|monkeys| trajectories(&monkeys, 1).map_err(|e| format!("{}: {}", file, e))) {
+            Ok(t) => t,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        if verbose >= 1 {
+            for (i, t) in t.iter().enumerate() {
+                println!("Item {} enters a cycle of {} rounds after {} rounds", i, t.cycle_length, t.cycle_start);
+            }
+        }
+        println!("Inspection count ({} rounds): {}", rounds, monkey_business(&t, rounds));
+        if let Some(log) = args.iter().find_map(|arg| arg.strip_prefix("--item-log=")) {
+            let log_rounds = args.iter()
+                .find_map(|arg| arg.strip_prefix("--log-rounds="))
+                .map_or(20, |r| r.parse::<u64>().expect("Log rounds is a number"));
+            fs::write(log, trajectory_csv(&t, log_rounds)).unwrap();
+        }
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--exact") {
+        let rounds = args.iter()
+            .find_map(|arg| arg.strip_prefix("--rounds="))
+            .map_or(20, |r| r.parse::<i32>().expect("Rounds is a number"));
+        for worriedness_factor in [3, 1] {
+            match solution_exact(file, rounds, worriedness_factor, verbose) {
+                Ok(count) => println!("Inspection count (exact, {} rounds): {}", rounds, count),
+                Err(e) => {
+                    println!("{}", e);
+                    return;
+                }
+            }
+            match check_modular(&mut parse_file(file).unwrap(), rounds, worriedness_factor) {
+                Ok(Ok(count)) => println!("Modular simulation agrees on all {} throws", count),
+                Ok(Err(m)) => println!("Modular simulation differs on throw {}: {:?} instead of {:?}", m.index, m.modular, m.exact),
+                Err(e) => println!("Modular simulation cannot run: {}", e),
+            }
+        }
+        return;
     }
 
-    println!("Inspection count: {}", solution(20, 3, verbose));
-    println!("Inspection count: {}", solution(10000, 1, verbose));
+    for (iterations, worriedness_factor) in [(20, 3), (10000, 1)] {
+        match solution(file, iterations, worriedness_factor, verbose) {
+            Ok(count) => println!("Inspection count: {}", count),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::solution;
+    use crate::{common_modulo, round, RunError, solution, solution_exact};
+    use crate::expr::EvalError;
+    use crate::parser::parse_notes;
 
     #[test]
     fn test_solution() {
-        let s = solution(20, 3, 0);
+        let s = solution("input.txt", 20, 3, 0).unwrap();
         assert_eq!(s, 55216);
+        assert_eq!(solution("test.txt", 20, 3, 0).unwrap(), 10605);
+        assert_eq!(solution_exact("input.txt", 20, 3, 0).unwrap(), 55216);
     }
 
     #[test]
     fn test_solution2() {
-        let s = solution(10000, 1, 0);
-        assert_eq!(s < 14400239985, true);
+        let s = solution("input.txt", 10000, 1, 0).unwrap();
+        assert!(s < 14400239985);
         assert_eq!(s, 12848882750);
+        assert_eq!(solution("test.txt", 10000, 1, 0).unwrap(), 2713310158);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_not_modular() {
+        let notes = "\
+Monkey 0:
+Starting items: 1
+Operation: new = old - 3
+Test: divisible by 2
+If true: throw to monkey 1
+If false: throw to monkey 1
+Monkey 1:
+Starting items: 10
+Operation: new = old + 1
+Test: divisible by 3
+If true: throw to monkey 0
+If false: throw to monkey 0";
+        let mut monkeys = parse_notes(notes).unwrap();
+        assert_eq!(common_modulo(&monkeys), Err(RunError::NotModular(0)));
+        assert_eq!(round(&mut monkeys, 1, 6), Err(RunError::Eval(0, EvalError::Underflow)));
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::expr::Expr;
+use crate::Monkey;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    ExpectedMonkey(String),
+    InvalidMonkey(String),
+    DuplicateMonkey(usize),
+    MissingMonkey(usize),
+    UnknownField(String),
+    DuplicateField(String),
+    MissingField(&'static str),
+    InvalidItem(String),
+    InvalidOperation(String),
+    InvalidTest(String),
+    InvalidTarget(String),
+    UnknownTarget(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::ExpectedMonkey(s) => write!(f, "expected a Monkey N: header, got {}", s),
+            ParseError::InvalidMonkey(s) => write!(f, "invalid monkey number {}", s),
+            ParseError::DuplicateMonkey(n) => write!(f, "monkey {} is described twice", n),
+            ParseError::MissingMonkey(n) => write!(f, "monkey {} is missing", n),
+            ParseError::UnknownField(s) => write!(f, "unknown field {}", s),
+            ParseError::DuplicateField(s) => write!(f, "field {} is given twice", s),
+            ParseError::MissingField(s) => write!(f, "missing field {}", s),
+            ParseError::InvalidItem(s) => write!(f, "invalid item {}", s),
+            ParseError::InvalidOperation(s) => write!(f, "invalid operation {}", s),
+            ParseError::InvalidTest(s) => write!(f, "invalid test {}", s),
+            ParseError::InvalidTarget(s) => write!(f, "invalid target {}", s),
+            ParseError::UnknownTarget(n) => write!(f, "there is no monkey {} to throw to", n),
+        }
+    }
+}
+
+pub const STARTING_ITEMS: &str = "Starting items";
+pub const OPERATION: &str = "Operation";
+pub const TEST: &str = "Test";
+pub const IF_TRUE: &str = "If true";
+pub const IF_FALSE: &str = "If false";
+
+/// The fields of one monkey read so far, the throw targets along with the line they are on.
+struct Block {
+    id: usize,
+    line: usize,
+    items: Option<Vec<u64>>,
+    operation: Option<Expr>,
+    test: Option<u64>,
+    true_monkey: Option<(usize, usize)>,
+    false_monkey: Option<(usize, usize)>,
+}
+
+impl Block {
+    fn new(id: usize, line: usize) -> Block {
+        Block { id, line, items: None, operation: None, test: None, true_monkey: None, false_monkey: None }
+    }
+
+    fn into_monkey(self) -> Result<Monkey, (usize, ParseError)> {
+        let missing = |field| (self.line, ParseError::MissingField(field));
+        Ok(Monkey {
+            items: self.items.ok_or_else(|| missing(STARTING_ITEMS))?,
+            operation: self.operation.ok_or_else(|| missing(OPERATION))?,
+            test: self.test.ok_or_else(|| missing(TEST))?,
+            true_monkey: self.true_monkey.ok_or_else(|| missing(IF_TRUE))?.0,
+            false_monkey: self.false_monkey.ok_or_else(|| missing(IF_FALSE))?.0,
+            inspection_count: 0,
+        })
+    }
+}
+
+fn parse_items(value: &str) -> Result<Vec<u64>, ParseError> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(|i| i.parse::<u64>().map_err(|_| ParseError::InvalidItem(i.to_string())))
+        .collect()
+}
+
+fn parse_operation(value: &str) -> Result<Expr, ParseError> {
+    let invalid = || ParseError::InvalidOperation(value.to_string());
+    let (new, expression) = value.split_once('=').ok_or_else(invalid)?;
+    if new.trim() != "new" {
+        return Err(invalid());
+    }
+    Expr::parse(expression).ok_or_else(invalid)
+}
+
+/// Parses the number after a fixed phrase, like the 19 of `divisible by 19`.
+fn parse_phrase<T: std::str::FromStr>(value: &str, phrase: &[&str]) -> Option<T> {
+    let mut words = value.split_whitespace();
+    for expected in phrase {
+        if words.next() != Some(*expected) {
+            return None;
+        }
+    }
+    let n = words.next()?.parse().ok()?;
+    words.next().is_none().then_some(n)
+}
+
+/// Parses the monkey notes.
+///
+/// Each monkey starts with a `Monkey N:` header followed by `label: value` fields in any
+/// order. Blank lines and indentation do not matter, monkeys can be listed in any order but
+/// have to be numbered from 0 without gaps, and every throw has to go to a monkey that exists.
+/// Errors carry the line number they were found on.
+pub fn parse_notes(notes: &str) -> Result<Vec<Monkey>, (usize, ParseError)> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for (n, l) in notes.lines().enumerate().map(|(n, l)| (n + 1, l.trim())) {
+        if l.is_empty() {
+            continue;
+        }
+        if let Some(id) = l.strip_prefix("Monkey ").and_then(|s| s.strip_suffix(':')) {
+            let id = id.trim().parse::<usize>().map_err(|_| (n, ParseError::InvalidMonkey(id.to_string())))?;
+            if blocks.iter().any(|b| b.id == id) {
+                return Err((n, ParseError::DuplicateMonkey(id)));
+            }
+            blocks.push(Block::new(id, n));
+            continue;
+        }
+
+        let block = blocks.last_mut().ok_or_else(|| (n, ParseError::ExpectedMonkey(l.to_string())))?;
+        let (label, value) = l.split_once(':').ok_or_else(|| (n, ParseError::UnknownField(l.to_string())))?;
+        let (label, value) = (label.trim(), value.trim());
+        let duplicate = || (n, ParseError::DuplicateField(label.to_string()));
+        match label {
+            STARTING_ITEMS if block.items.is_some() => return Err(duplicate()),
+            STARTING_ITEMS => block.items = Some(parse_items(value).map_err(|e| (n, e))?),
+            OPERATION if block.operation.is_some() => return Err(duplicate()),
+            OPERATION => block.operation = Some(parse_operation(value).map_err(|e| (n, e))?),
+            TEST if block.test.is_some() => return Err(duplicate()),
+            TEST => block.test = Some(
+                parse_phrase(value, &["divisible", "by"])
+                    .filter(|d| *d > 0)
+                    .ok_or_else(|| (n, ParseError::InvalidTest(value.to_string())))?
+            ),
+            IF_TRUE | IF_FALSE => {
+                let target = parse_phrase(value, &["throw", "to", "monkey"])
+                    .ok_or_else(|| (n, ParseError::InvalidTarget(value.to_string())))?;
+                let slot = if label == IF_TRUE { &mut block.true_monkey } else { &mut block.false_monkey };
+                if slot.is_some() {
+                    return Err(duplicate());
+                }
+                *slot = Some((target, n));
+            }
+            _ => return Err((n, ParseError::UnknownField(label.to_string()))),
+        }
+    }
+
+    let count = blocks.len();
+    blocks.sort_by_key(|b| b.id);
+    if let Some((expected, _)) = blocks.iter().enumerate().find(|(i, b)| b.id != *i) {
+        return Err((notes.lines().count(), ParseError::MissingMonkey(expected)));
+    }
+    let mut monkeys = Vec::new();
+    for block in blocks {
+        for (target, line) in [block.true_monkey, block.false_monkey].into_iter().flatten() {
+            if target >= count {
+                return Err((line, ParseError::UnknownTarget(target)));
+            }
+        }
+        monkeys.push(block.into_monkey()?);
+    }
+    Ok(monkeys)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::{parse_notes, ParseError};
+
+    fn error(notes: &str) -> Option<(usize, ParseError)> {
+        parse_notes(notes).err()
+    }
+
+    #[test]
+    fn test_layout() {
+        // Fields out of order, odd indentation, no blank lines and no trailing newline
+        let notes = "\
+Monkey 1:
+Test: divisible by 3
+  If false:   throw to monkey 0
+Operation: new = 2 * old
+    Starting items: 5
+If true: throw to monkey 0
+Monkey 0:
+  Starting items: 1,2 ,  3
+  Operation: new = old * old
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1";
+        let monkeys = parse_notes(notes).unwrap();
+        assert_eq!(monkeys.len(), 2);
+        assert_eq!(monkeys[0].items, vec![1, 2, 3]);
+        assert_eq!(monkeys[0].operation.eval(7), Ok(49));
+        assert_eq!(monkeys[1].items, vec![5]);
+        assert_eq!(monkeys[1].operation.eval(7), Ok(14));
+        assert_eq!((monkeys[1].test, monkeys[1].true_monkey, monkeys[1].false_monkey), (3, 0, 0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let monkey = |id: usize, target: usize| format!(
+            "Monkey {}:\nStarting items: 1\nOperation: new = old + 1\nTest: divisible by 2\nIf true: throw to monkey {}\nIf false: throw to monkey 0\n",
+            id, target
+        );
+        assert!(parse_notes(&(monkey(0, 1) + &monkey(1, 0))).is_ok());
+        assert_eq!(error(&(monkey(0, 1) + &monkey(1, 2))), Some((11, ParseError::UnknownTarget(2))));
+        assert_eq!(error(&(monkey(0, 0) + &monkey(2, 0))), Some((12, ParseError::MissingMonkey(1))));
+        assert_eq!(error(&(monkey(0, 0) + &monkey(0, 0))), Some((7, ParseError::DuplicateMonkey(0))));
+        assert_eq!(error("Monkey 0:\nTest: divisible by 2"), Some((1, ParseError::MissingField("Starting items"))));
+        assert_eq!(error("Starting items: 1"), Some((1, ParseError::ExpectedMonkey(String::from("Starting items: 1")))));
+        assert_eq!(error("Monkey one:"), Some((1, ParseError::InvalidMonkey(String::from("one")))));
+        assert_eq!(error("Monkey 0:\nTest: divisible by 2\nTest: divisible by 3"), Some((3, ParseError::DuplicateField(String::from("Test")))));
+        assert_eq!(error("Monkey 0:\n  Starting items: 1, x"), Some((2, ParseError::InvalidItem(String::from("x")))));
+        assert_eq!(error("Monkey 0:\nOperation: new = old ^ 2"), Some((2, ParseError::InvalidOperation(String::from("new = old ^ 2")))));
+        assert_eq!(error("Monkey 0:\nTest: divisible by 0"), Some((2, ParseError::InvalidTest(String::from("divisible by 0")))));
+        assert_eq!(error("Monkey 0:\nIf true: throw to 1"), Some((2, ParseError::InvalidTarget(String::from("throw to 1")))));
+        assert_eq!(error("Monkey 0:\nColour: brown"), Some((2, ParseError::UnknownField(String::from("Colour")))));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use crate::{common_modulo, Monkey, round, RunError};
+
+/// What happened during a simulation, round by round.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    /// Items inspected by every monkey during each round
+    pub inspections: Vec<Vec<u64>>,
+    /// How often every monkey threw to every other, `throws[from][to]`
+    pub throws: Vec<Vec<u64>>,
+    /// Items held by every monkey at the start and after each round
+    pub held: Vec<Vec<usize>>,
+}
+
+impl Report {
+    /// Runs `rounds` rounds on the monkeys and records them.
+    pub fn collect(monkeys: &mut [Monkey], rounds: usize, worriedness_factor: u64) -> Result<Report, RunError> {
+        let common_modulo = common_modulo(monkeys)?;
+        let n = monkeys.len();
+        let mut report = Report {
+            inspections: Vec::new(),
+            throws: vec![vec![0; n]; n],
+            held: vec![monkeys.iter().map(|m| m.items.len()).collect()],
+        };
+        for _ in 0..rounds {
+            let mut inspections = vec![0; n];
+            for (from, to) in round(monkeys, worriedness_factor, common_modulo)? {
+                inspections[from] += 1;
+                report.throws[from][to] += 1;
+            }
+            report.inspections.push(inspections);
+            report.held.push(monkeys.iter().map(|m| m.items.len()).collect());
+        }
+        Ok(report)
+    }
+
+    pub fn total_inspections(&self) -> Vec<u64> {
+        let mut totals = vec![0; self.throws.len()];
+        for round in self.inspections.iter() {
+            totals.iter_mut().zip(round).for_each(|(total, i)| *total += i);
+        }
+        totals
+    }
+
+    /// The product of the two highest inspection counts.
+    pub fn monkey_business(&self) -> u64 {
+        let mut totals = self.total_inspections();
+        totals.sort_unstable_by(|a, b| b.cmp(a));
+        totals.iter().take(2).product()
+    }
+
+    /// For one monkey, the number of rounds it ended holding each number of items.
+    pub fn held_distribution(&self, monkey: usize) -> BTreeMap<usize, usize> {
+        let mut distribution = BTreeMap::new();
+        for held in self.held.iter().skip(1) {
+            *distribution.entry(held[monkey]).or_insert(0) += 1;
+        }
+        distribution
+    }
+
+    fn header(&self, first: &str) -> String {
+        let monkeys: Vec<String> = (0..self.throws.len()).map(|i| format!("monkey{}", i)).collect();
+        format!("{},{}\n", first, monkeys.join(","))
+    }
+
+    fn csv<T: ToString>(&self, first: &str, rows: &[Vec<T>], offset: usize) -> String {
+        let mut csv = self.header(first);
+        for (i, row) in rows.iter().enumerate() {
+            let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+            csv.push_str(&format!("{},{}\n", i + offset, row.join(",")));
+        }
+        csv
+    }
+
+    /// One line per round, with the inspections of every monkey.
+    pub fn inspections_csv(&self) -> String {
+        self.csv("round", &self.inspections, 1)
+    }
+
+    /// One line per throwing monkey, with the throws to every monkey.
+    pub fn throws_csv(&self) -> String {
+        self.csv("from", &self.throws, 0)
+    }
+
+    /// One line per round, with the items every monkey holds after it. Round 0 is the start.
+    pub fn held_csv(&self) -> String {
+        self.csv("round", &self.held, 0)
+    }
+
+    pub fn summary(&self) -> String {
+        let totals = self.total_inspections();
+        let mut out = format!("{} rounds, monkey business {}\n", self.inspections.len(), self.monkey_business());
+        out.push_str("monkey  inspections  throws to                 held min/max/mean\n");
+        for (i, total) in totals.iter().enumerate() {
+            let targets: Vec<String> = self.throws[i].iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(to, count)| format!("{}:{}", to, count))
+                .collect();
+            let distribution = self.held_distribution(i);
+            let rounds: usize = distribution.values().sum();
+            let (min, max) = (distribution.keys().next().unwrap_or(&0), distribution.keys().last().unwrap_or(&0));
+            let mean = distribution.iter().map(|(k, v)| k * v).sum::<usize>() as f64 / rounds.max(1) as f64;
+            out.push_str(&format!("{:>6}  {:>11}  {:<24}  {}/{}/{:.2}\n", i, total, targets.join(" "), min, max, mean));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_file;
+    use crate::report::Report;
+
+    #[test]
+    fn test_report() {
+        let mut monkeys = parse_file("test.txt").unwrap();
+        let report = Report::collect(&mut monkeys, 20, 3).unwrap();
+        assert_eq!(report.total_inspections(), vec![101, 95, 7, 105]);
+        assert_eq!(report.monkey_business(), 10605);
+        assert_eq!(report.inspections[0], vec![2, 4, 3, 5]);
+        // Every inspection ends with a throw
+        assert_eq!(report.throws.iter().map(|t| t.iter().sum::<u64>()).collect::<Vec<u64>>(), report.total_inspections());
+        assert_eq!(report.throws[2], vec![0, 4, 0, 3]);
+        // Items are neither lost nor created
+        assert!(report.held.iter().all(|h| h.iter().sum::<usize>() == 10));
+        assert_eq!(report.held[1], vec![4, 6, 0, 0]);
+        assert_eq!(report.held_distribution(2).get(&0), Some(&20));
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut monkeys = parse_file("test.txt").unwrap();
+        let report = Report::collect(&mut monkeys, 2, 3).unwrap();
+        assert_eq!(report.inspections_csv(), "round,monkey0,monkey1,monkey2,monkey3\n1,2,4,3,5\n2,4,6,1,5\n");
+        assert_eq!(report.held_csv().lines().nth(1), Some("0,2,4,3,1"));
+        assert_eq!(report.throws_csv().lines().count(), 5);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{common_modulo, Monkey, RunError};
+
+/// The path of a single item from round to round.
+///
+/// Items never interact, and an item's (monkey, worry level modulo the common modulo) at the
+/// start of a round decides everything it goes through, so following it until that state
+/// comes back gives its whole future: a lead-in of `cycle_start` rounds and then a cycle of
+/// `cycle_length` rounds repeating forever.
+///
+/// This only holds for a worriedness factor of 1. Dividing by another factor does not carry
+/// over to reduced worry levels, so then the trajectory is only right while the exact worry
+/// levels stay below the common modulo, the same as for `solution`.
+pub struct Trajectory {
+    /// Monkey holding the item after every round, starting with the one holding it at first
+    pub holders: Vec<usize>,
+    /// Inspections by every monkey during the first r rounds
+    prefix: Vec<Vec<u64>>,
+    pub cycle_start: usize,
+    pub cycle_length: usize,
+}
+
+impl Trajectory {
+    pub fn follow(monkeys: &[Monkey], monkey: usize, worry: u64, worriedness_factor: u64, common_modulo: u64) -> Result<Trajectory, RunError> {
+        let mut seen = HashMap::new();
+        let mut state = (monkey, worry % common_modulo);
+        let mut holders = Vec::new();
+        let mut prefix = vec![vec![0; monkeys.len()]];
+        while !seen.contains_key(&state) {
+            seen.insert(state, holders.len());
+            holders.push(state.0);
+            let mut inspections = prefix.last().unwrap().clone();
+            // The item keeps moving this round as long as it goes to a monkey whose turn is still
+            // to come, or back to the same monkey, which inspects it again right away
+            let (mut monkey, mut worry) = state;
+            let mut kept = HashSet::new();
+            loop {
+                inspections[monkey] += 1;
+                let (target, n) = monkeys[monkey].inspect(worry, worriedness_factor, common_modulo)
+                    .map_err(|e| RunError::Eval(monkey, e))?;
+                if target != monkey {
+                    kept.clear();
+                } else if !kept.insert(n) {
+                    return Err(RunError::Endless(monkey));
+                }
+                let later = target >= monkey;
+                (monkey, worry) = (target, n);
+                if !later {
+                    break;
+                }
+            }
+            state = (monkey, worry);
+            prefix.push(inspections);
+        }
+        let cycle_start = seen[&state];
+        Ok(Trajectory { holders, prefix, cycle_start, cycle_length: seen.len() - cycle_start })
+    }
+
+    /// Index of the recorded round that `round` repeats.
+    fn position(&self, round: u64) -> usize {
+        let start = self.cycle_start as u64;
+        if round < start {
+            round as usize
+        } else {
+            (start + (round - start) % self.cycle_length as u64) as usize
+        }
+    }
+
+    /// Monkey holding the item after `round` rounds.
+    pub fn holder(&self, round: u64) -> usize {
+        self.holders[self.position(round)]
+    }
+
+    /// Inspections of the item by every monkey during the first `rounds` rounds.
+    pub fn inspections(&self, rounds: u64) -> Vec<u64> {
+        if rounds < self.prefix.len() as u64 {
+            return self.prefix[rounds as usize].clone();
+        }
+        let (start, end) = (&self.prefix[self.cycle_start], &self.prefix[self.cycle_start + self.cycle_length]);
+        let cycles = (rounds - self.cycle_start as u64) / self.cycle_length as u64;
+        let partial = &self.prefix[self.position(rounds)];
+        partial.iter()
+            .zip(start.iter().zip(end.iter()))
+            .map(|(p, (s, e))| p + cycles * (e - s))
+            .collect()
+    }
+}
+
+/// Follows every item, in the order the monkeys hold them in the notes.
+pub fn trajectories(monkeys: &[Monkey], worriedness_factor: u64) -> Result<Vec<Trajectory>, RunError> {
+    let common_modulo = common_modulo(monkeys)?;
+    monkeys.iter()
+        .enumerate()
+        .flat_map(|(i, m)| m.items.iter().map(move |worry| (i, *worry)))
+        .map(|(i, worry)| Trajectory::follow(monkeys, i, worry, worriedness_factor, common_modulo))
+        .collect()
+}
+
+/// The product of the two highest inspection counts after `rounds` rounds.
+pub fn monkey_business(trajectories: &[Trajectory], rounds: u64) -> u128 {
+    let mut totals: Vec<u64> = Vec::new();
+    for t in trajectories {
+        let inspections = t.inspections(rounds);
+        totals.resize(inspections.len(), 0);
+        totals.iter_mut().zip(inspections).for_each(|(total, i)| *total += i);
+    }
+    totals.sort_unstable_by(|a, b| b.cmp(a));
+    totals.iter().take(2).map(|t| *t as u128).product()
+}
+
+/// Which monkey holds every item after each of the first `rounds` rounds, as `item,round,monkey` lines.
+pub fn trajectory_csv(trajectories: &[Trajectory], rounds: u64) -> String {
+    let mut csv = String::from("item,round,monkey\n");
+    for (item, t) in trajectories.iter().enumerate() {
+        for round in 0..=rounds {
+            csv.push_str(&format!("{},{},{}\n", item, round, t.holder(round)));
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_file, round, RunError, solution};
+    use crate::exact::simulate_exact;
+    use crate::parser::parse_notes;
+    use crate::report::Report;
+    use crate::trajectory::{monkey_business, trajectories, trajectory_csv};
+
+    #[test]
+    fn test_monkey_business() {
+        let monkeys = parse_file("test.txt").unwrap();
+        assert_eq!(monkey_business(&trajectories(&monkeys, 3).unwrap(), 20), 10605);
+        assert_eq!(monkey_business(&trajectories(&monkeys, 1).unwrap(), 10000), 2713310158);
+
+        let monkeys = parse_file("input.txt").unwrap();
+        assert_eq!(monkey_business(&trajectories(&monkeys, 3).unwrap(), 20), 55216);
+        let t = trajectories(&monkeys, 1).unwrap();
+        for rounds in [1, 500, 1000, 10000] {
+            assert_eq!(monkey_business(&t, rounds), solution("input.txt", rounds as i32, 1, 0).unwrap() as u128);
+        }
+        assert_eq!(monkey_business(&t, 1_000_000_000_000), 128534557340799601276852200);
+    }
+
+    #[test]
+    fn test_holders() {
+        let mut monkeys = parse_file("test.txt").unwrap();
+        let t = trajectories(&monkeys, 1).unwrap();
+        assert_eq!(t.iter().map(|t| t.holder(0)).collect::<Vec<usize>>(), vec![0, 0, 1, 1, 1, 1, 2, 2, 2, 3]);
+        // Compare with where the round by round simulation puts the items
+        for r in 1..=1000 {
+            round(&mut monkeys, 1, 96577).unwrap();
+            let mut held: Vec<usize> = t.iter().map(|t| t.holder(r)).collect();
+            held.sort();
+            let expected: Vec<usize> = monkeys.iter().enumerate().flat_map(|(i, m)| vec![i; m.items.len()]).collect();
+            assert_eq!(held, expected);
+        }
+        let csv = trajectory_csv(&t[..1], 2);
+        assert_eq!(csv.lines().count(), 4);
+        assert_eq!(csv.lines().nth(1), Some("0,0,0"));
+    }
+
+    #[test]
+    fn test_throw_to_itself() {
+        let notes = |operation: &str| format!("\
+Monkey 0:
+Starting items: 1, 2
+Operation: new = {}
+Test: divisible by 2
+If true: throw to monkey 0
+If false: throw to monkey 1
+Monkey 1:
+Starting items: 3
+Operation: new = old + 2
+Test: divisible by 3
+If true: throw to monkey 0
+If false: throw to monkey 1", operation);
+        let monkeys = parse_notes(&notes("old + 1")).unwrap();
+        let t = trajectories(&monkeys, 1).unwrap();
+        for rounds in 1..=50 {
+            let report = Report::collect(&mut parse_notes(&notes("old + 1")).unwrap(), rounds, 1).unwrap();
+            assert_eq!(monkey_business(&t, rounds as u64), report.monkey_business() as u128, "{}", rounds);
+        }
+
+        // An even item is thrown back to monkey 0 forever
+        let mut monkeys = parse_notes(&notes("old * 1")).unwrap();
+        assert_eq!(trajectories(&monkeys, 1).err(), Some(RunError::Endless(0)));
+        assert_eq!(simulate_exact(&monkeys, 1, 1).err(), Some(RunError::Endless(0)));
+        assert_eq!(round(&mut monkeys, 1, 6), Err(RunError::Endless(0)));
+
+        // An even item keeps growing while monkey 0 throws it back, so it never repeats exactly
+        let mut monkeys = parse_notes(&notes("old + 2")).unwrap();
+        assert_eq!(trajectories(&monkeys, 1).err(), Some(RunError::Endless(0)));
+        assert_eq!(simulate_exact(&monkeys, 1, 1).err(), Some(RunError::Endless(0)));
+        assert_eq!(round(&mut monkeys, 1, 6), Err(RunError::Endless(0)));
+    }
+}
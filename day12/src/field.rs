@@ -0,0 +1,170 @@
+use search::{Grid, Search};
+
+use crate::get_neighbours;
+use crate::terrain::Rule;
+
+/// Height of a cell, with the start at the lowest and the end at the highest level.
+pub fn height(c: u8) -> u8 {
+    match c {
+        b'S' => b'a',
+        b'E' => b'z',
+        c => c,
+    }
+}
+
+/// A connected area of cells from which the end cannot be reached.
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    /// The cells, row by row
+    pub cells: Vec<(usize, usize)>,
+    pub lowest: u8,
+    pub highest: u8,
+}
+
+/// The number of steps from every cell of the map to the end.
+///
+/// The search goes backwards from the end, so one pass answers the distance of any cell.
+pub struct DistanceField {
+    heights: Vec<Vec<u8>>,
+    end: (usize, usize),
+    search: Search<(usize, usize), Grid>,
+}
+
+impl DistanceField {
+    /// Searches from the cell marked `E` with steps allowed by `rule`, or returns `None` if there is no end.
+    pub fn new(map: &[Vec<u8>], rule: Rule) -> Option<DistanceField> {
+        let end = map.iter()
+            .enumerate()
+            .find_map(|(y, row)| row.iter().position(|c| *c == b'E').map(|x| (x, y)))?;
+        let heights: Vec<Vec<u8>> = map.iter().map(|row| row.iter().map(|c| height(*c)).collect()).collect();
+        let search = Search::with_store(Grid::new(heights[0].len(), heights.len()))
+            .bfs([end], |&(x, y)| get_neighbours(&heights, x, y, |a, b| rule.can_travel(a, b, -1)), |_| false);
+        Some(DistanceField { heights, end, search })
+    }
+
+    /// Steps from (x, y) to the end, if it can get there.
+    pub fn distance(&self, x: usize, y: usize) -> Option<u64> {
+        self.search.distance(&(x, y))
+    }
+
+    /// The cell of height `h` closest to the end, with its distance. Ties go to the first cell row by row.
+    pub fn closest(&self, h: char) -> Option<((usize, usize), u64)> {
+        self.cells()
+            .filter(|(x, y)| self.heights[*y][*x] == h as u8)
+            .filter_map(|(x, y)| Some(((x, y), self.distance(x, y)?)))
+            .min_by_key(|(_, d)| *d)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.heights[0].len();
+        (0..self.heights.len()).flat_map(move |y| (0..width).map(move |x| (x, y)))
+    }
+
+    /// The distances as a plain PGM image, brighter closer to the end and black where it cannot be reached.
+    pub fn heatmap(&self) -> String {
+        let max = self.search.distances().map(|(_, d)| d).max().unwrap_or(0).max(1);
+        let mut pgm = format!("P2\n{} {}\n255\n", self.heights[0].len(), self.heights.len());
+        for y in 0..self.heights.len() {
+            let row: Vec<String> = (0..self.heights[0].len())
+                .map(|x| self.distance(x, y).map_or(0, |d| 255 - d * 254 / max).to_string())
+                .collect();
+            pgm.push_str(&row.join(" "));
+            pgm.push('\n');
+        }
+        pgm
+    }
+
+    /// The areas the end cannot be reached from, in the order of their first cell row by row.
+    pub fn unreachable(&self) -> Vec<Region> {
+        let (width, height) = (self.heights[0].len(), self.heights.len());
+        let mut seen = vec![vec![false; width]; height];
+        let mut regions = Vec::new();
+        for (x, y) in self.cells() {
+            if seen[y][x] || self.distance(x, y).is_some() {
+                continue;
+            }
+            let neighbours = |&(x, y): &(usize, usize)| {
+                [(x + 1, y), (x.wrapping_sub(1), y), (x, y + 1), (x, y.wrapping_sub(1))]
+                    .into_iter()
+                    .filter(|&(x, y)| x < width && y < height && self.distance(x, y).is_none())
+            };
+            let mut cells: Vec<(usize, usize)> = search::bfs([(x, y)], neighbours, |_| false)
+                .distances()
+                .map(|(cell, _)| cell)
+                .collect();
+            cells.sort_by_key(|(x, y)| (*y, *x));
+            let heights = cells.iter().map(|(x, y)| self.heights[*y][*x]);
+            let (lowest, highest) = (heights.clone().min().unwrap(), heights.max().unwrap());
+            cells.iter().for_each(|(x, y)| seen[*y][*x] = true);
+            regions.push(Region { cells, lowest, highest });
+        }
+        regions
+    }
+
+    /// One line per unreachable region.
+    pub fn report(&self) -> String {
+        let regions = self.unreachable();
+        let cells: usize = regions.iter().map(|r| r.cells.len()).sum();
+        let mut out = format!("{} cells in {} regions cannot reach the end at {:?}\n", cells, regions.len(), self.end);
+        for r in regions {
+            out.push_str(&format!(
+                "  {} cells from {:?}, heights {}-{}\n",
+                r.cells.len(), r.cells[0], r.lowest as char, r.highest as char
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::field::{DistanceField, Region};
+    use crate::{parse_file, search};
+    use crate::terrain::Rule;
+
+    fn field(file: &str) -> DistanceField {
+        DistanceField::new(&parse_file(file, 'S').0, Rule::default()).unwrap()
+    }
+
+    #[test]
+    fn test_distance_field() {
+        let field = field("test.txt");
+        assert_eq!(field.end, (5, 2));
+        assert_eq!(field.distance(0, 0), Some(31));
+        assert_eq!(field.closest('a'), Some(((0, 4), 29)));
+        assert_eq!(field.closest('z'), Some(((5, 2), 0)));
+        assert_eq!(field.closest('A'), None);
+        assert!(field.unreachable().is_empty());
+
+        let heatmap = field.heatmap();
+        assert_eq!(heatmap.lines().take(3).collect::<Vec<&str>>(), vec!["P2", "8 5", "255"]);
+        assert_eq!(heatmap.lines().nth(5).unwrap().split(' ').nth(5), Some("255"));
+    }
+
+    #[test]
+    fn test_closest_matches_search() {
+        let field = field("input.txt");
+        let (map, s) = parse_file("input.txt", 'S');
+        let (x, y) = s.unwrap();
+        assert_eq!(field.distance(x, y), Some(456));
+        assert_eq!(field.closest('a').map(|(_, d)| d), Some(454));
+        for h in 'a'..='z' {
+            let ((x, y), d) = field.closest(h).unwrap();
+            assert_eq!(search(&map, x, y, 'E').map(|p| p.len() as u64 - 1), Some(d), "{}", h);
+        }
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let map: Vec<Vec<u8>> = ["yzE", "aaz", "aza"].iter().map(|r| r.bytes().collect()).collect();
+        let field = DistanceField::new(&map, Rule::default()).unwrap();
+        assert_eq!(field.distance(0, 0), Some(2));
+        assert_eq!(field.unreachable(), vec![Region {
+            cells: vec![(0, 1), (1, 1), (0, 2), (1, 2), (2, 2)],
+            lowest: b'a',
+            highest: b'z',
+        }]);
+        assert_eq!(field.report().lines().nth(1), Some("  5 cells from (0, 1), heights a-z"));
+        assert!(field.heatmap().ends_with("\n0 0 0\n"));
+    }
+}
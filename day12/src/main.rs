@@ -1,7 +1,16 @@
-use std::collections::VecDeque;
+use std::{env, fs};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::time::Instant;
+
+use search::{Grid, Search};
+
+use crate::field::DistanceField;
+use crate::terrain::{Cost, Rule, Terrain};
+
+mod field;
+mod terrain;
 
 static VERBOSE: u32 = 0;
 
@@ -10,7 +19,7 @@ fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader
     Ok(io::BufReader::new(file).lines())
 }
 
-fn parse_file(file: &str, start: char, direction: i32) -> (Vec<Vec<u8>>, Option<(usize, usize)>) {
+fn parse_file(file: &str, start: char) -> (Vec<Vec<u8>>, Option<(usize, usize)>) {
     let mut map = Vec::new();
     let mut s = None;
     if let Ok(lines) = read_lines(file) {
@@ -20,11 +29,7 @@ fn parse_file(file: &str, start: char, direction: i32) -> (Vec<Vec<u8>>, Option<
                 for (x, c) in l.bytes().enumerate() {
                     if c == start as u8 {
                         s = Some((x, y));
-                        if direction > 0 {
-                            row.push('a' as u8);
-                        } else {
-                            row.push('z' as u8);
-                        }
+                        row.push(b'a');
                     } else {
                         row.push(c);
                     }
@@ -36,18 +41,13 @@ fn parse_file(file: &str, start: char, direction: i32) -> (Vec<Vec<u8>>, Option<
     (map, s)
 }
 
-fn can_travel(a: u8, mut b: u8, direction: i32) -> bool {
-    if b == 'E' as u8 {
-        b = 'z' as u8;
-    }
-    if direction > 0 {
-        a + 1 >= b
-    } else {
-        a - 1 <= b
-    }
+/// Whether a search can go from cell `a` to cell `b` under the puzzle rule.
+fn can_travel(a: u8, b: u8, direction: i32) -> bool {
+    Rule::default().can_travel(a, b, direction)
 }
 
-fn get_neighbours(map: &Vec<Vec<u8>>, x: usize, y: usize, direction: i32) -> Vec<(usize, usize)> {
+/// The cells next to (x, y) that `allowed` lets a search step to, given both cells.
+fn get_neighbours(map: &[Vec<u8>], x: usize, y: usize, allowed: impl Fn(u8, u8) -> bool) -> Vec<(usize, usize)> {
     let mut n = Vec::new();
 
     if VERBOSE >= 2 {
@@ -64,68 +64,86 @@ fn get_neighbours(map: &Vec<Vec<u8>>, x: usize, y: usize, direction: i32) -> Vec
             println!("Scanning y-1: {} >= {}", map[y][x] as char, map[y - 1][x] as char);
         }
     }
-    if x < map[0].len()-1 && can_travel(map[y][x], map[y][x + 1], direction) {
+    if x < map[0].len()-1 && allowed(map[y][x], map[y][x + 1]) {
         n.push((x + 1, y));
     }
-    if x > 0 && can_travel(map[y][x], map[y][x - 1], direction) {
+    if x > 0 && allowed(map[y][x], map[y][x - 1]) {
         n.push((x - 1, y));
     }
-    if y < map.len() -1 && can_travel(map[y][x], map[y + 1][x], direction) {
+    if y < map.len() -1 && allowed(map[y][x], map[y + 1][x]) {
         n.push((x, y + 1));
     }
-    if y > 0 && can_travel(map[y][x], map[y - 1][x], direction) {
+    if y > 0 && allowed(map[y][x], map[y - 1][x]) {
         n.push((x, y - 1));
     }
     n
 }
 
-fn search(map: &mut Vec<Vec<u8>>, x: usize, y: usize, end: char, direction: i32) -> Option<Vec<(usize, usize)>> {
-    let mut q = VecDeque::new();
-
-    let mut path = Vec::new();
-    path.push((x, y));
-    q.push_back(path);
-
-    while let Some(path) = q.pop_front() {
-        let (x, y) = path.get(path.len() - 1).unwrap();
+/// Breadth first search from (x, y) to the closest cell marked `end`, returning the path.
+fn search(map: &[Vec<u8>], x: usize, y: usize, end: char) -> Option<Vec<(usize, usize)>> {
+    let neighbours = |&(x, y): &(usize, usize)| {
         if VERBOSE >= 2 { println!("standing at ({}, {})", x, y); }
-        if map[*y][*x] == end as u8 {
-            if VERBOSE >= 2 { println!("Solution found {}, {}", map[*y][*x], end as u8); }
-            return Some(path);
-        }
-        if map[*y][*x] != 255 && map[*y][*x] != 0 {
-            for (nx, ny) in get_neighbours(map, *x, *y, direction) {
-                if VERBOSE >= 2 { println!("N: {} {} ", nx, ny); }
-                let mut p = path.clone();
-                p.push((nx, ny));
-                q.push_back(p);
-            }
-            if direction > 0 {
-                map[*y][*x] = 255;
-            } else {
-                map[*y][*x] = 0;
-            }
+        get_neighbours(map, x, y, |a, b| can_travel(a, b, 1))
+    };
+    let found = Search::with_store(Grid::new(map[0].len(), map.len()))
+        .bfs([(x, y)], neighbours, |&(x, y)| map[y][x] == end as u8);
+    if VERBOSE >= 2 && found.goal.is_some() { println!("Solution found after reaching {} cells", found.distances().count()); }
+    found.path()
+}
+
+/// A `width` x `height` map climbing from `a` on the left to `z` on the right, one level at a
+/// time, with random pits that cannot be climbed out of on the way up. The top row and the
+/// right column have no pits, so the end in the bottom right corner can always be reached.
+fn generate_map(width: usize, height: usize, seed: u64) -> Vec<Vec<u8>> {
+    let mut state = seed;
+    let mut random = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        state >> 33
+    };
+    let mut map = vec![vec![b'a'; width]; height];
+    for (y, row) in map.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            let level = (x * 26 / width).min(25) as u8;
+            let pit = if y > 0 && x < width - 1 && random() % 4 == 0 { (random() % 3) as u8 } else { 0 };
+            *cell = b'a' + level.saturating_sub(pit);
         }
     }
-    None
+    map[height - 1][width - 1] = b'E';
+    map
+}
+
+/// Times the search across generated maps of growing size.
+fn benchmark(sizes: &[usize]) {
+    for size in sizes {
+        let map = generate_map(size.max(&26) * 2, *size, 12);
+        let start = Instant::now();
+        let path = search(&map, 0, 0, 'E');
+        println!(
+            "{}x{}: path of {:?} steps in {:?}",
+            map[0].len(),
+            map.len(),
+            path.map(|p| p.len() - 1),
+            start.elapsed()
+        );
+    }
 }
 
-fn solution(file: &str, start: char, end: char, direction: i32) -> usize {
-    let (mut map, s) = parse_file(file, start, direction);
+fn solution(file: &str, start: char, end: char) -> usize {
+    let (map, s) = parse_file(file, start);
     if let Some((x, y)) = s {
         if VERBOSE >= 1 { println!("Start = ({}, {})", x, y); }
-        let s = search(&mut map, x, y, end, direction).unwrap();
+        let s = search(&map, x, y, end).unwrap();
         if VERBOSE >= 1 {
             // for (px, py) in s.iter() {
             //     println!("{}, {}", px, py);
             // }
-            let (mut map, _start) = parse_file(file, start, direction);
+            let (mut map, _start) = parse_file(file, start);
             for (px, py) in s.iter() {
-                map[*py][*px] = '#' as u8;
+                map[*py][*px] = b'#';
             }
-            for y in 0..map.len() {
-                for x in 0..map[0].len() {
-                    print!("{}",map[y][x] as char);
+            for row in map.iter() {
+                for c in row.iter() {
+                    print!("{}", *c as char);
                 }
                 println!()
             }
@@ -138,17 +156,63 @@ fn solution(file: &str, start: char, end: char, direction: i32) -> usize {
     0
 }
 
+/// Fewest steps to the end from any cell of height `h`, from a single search back from the end.
+fn closest(file: &str, h: char) -> usize {
+    let (map, _) = parse_file(file, 'S');
+    DistanceField::new(&map, Rule::default())
+        .and_then(|field| field.closest(h))
+        .map_or(0, |(_, d)| d as usize)
+}
+
 fn main() {
-    println!("Test Solution 1: {}", solution("test.txt",'S', 'E', 1));
-    println!("Test Solution 2: {}", solution("test.txt", 'E', 'a', -1));
+    let args: Vec<String> = env::args().collect();
+    if let Some(sizes) = args.iter().find_map(|arg| arg.strip_prefix("--bench=")) {
+        let sizes: Vec<usize> = sizes.split(',').map(|s| s.parse().expect("Sizes are numbers")).collect();
+        benchmark(&sizes);
+        return;
+    }
+
+    println!("Test Solution 1: {}", solution("test.txt",'S', 'E'));
+    println!("Test Solution 2: {}", closest("test.txt", 'a'));
+
+    println!("Solution 1: {}", solution("input.txt",'S', 'E'));
+    println!("Solution 2: {}", closest("input.txt", 'a'));
+
+    let rule = args.iter()
+        .find_map(|arg| arg.strip_prefix("--rule="))
+        .map_or(Rule::default(), |r| Rule::parse(r).expect("Rules are climb:K, descent:K or symmetric:K"));
+    let cost = args.iter()
+        .find_map(|arg| arg.strip_prefix("--cost="))
+        .map_or(Cost::default(), |c| Cost::parse(c).expect("Costs are unit, climb:K or slope:K"));
+    let terrain = Terrain { rule, cost };
+    let (map, s) = parse_file("input.txt", 'S');
+    if terrain != Terrain::default() {
+        let (x, y) = s.expect("The map has a start");
+        match terrain.cheapest(&map, x, y, 'E') {
+            Some((cost, path)) => println!("{:?}: cost {} in {} steps", terrain, cost, path.len() - 1),
+            None => println!("{:?}: the end cannot be reached", terrain),
+        }
+    }
 
-    println!("Solution 1: {}", solution("input.txt",'S', 'E', 1));
-    println!("Solution 2: {}", solution("input.txt", 'E', 'a', -1));
+    // Every question about reaching the end of the input, from a single search
+    let field = DistanceField::new(&map, rule).expect("The map has an end");
+    if VERBOSE >= 1 {
+        for (h, ((x, y), d)) in ('a'..='z').filter_map(|h| Some((h, field.closest(h)?))) {
+            println!("Closest {}: ({}, {}) in {} steps", h, x, y, d);
+        }
+    }
+    if args.iter().any(|arg| arg == "--unreachable") {
+        print!("{}", field.report());
+    }
+    if let Some(file) = args.iter().find_map(|arg| arg.strip_prefix("--heatmap=")) {
+        fs::write(file, field.heatmap()).unwrap();
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
-    use crate::{can_travel, solution};
+    use crate::{can_travel, closest, generate_map, search, solution};
     use more_asserts::{assert_le, assert_ge};
 
     #[test]
@@ -171,35 +235,49 @@ mod test {
 
     #[test]
     fn test_solution() {
-        assert_eq!(solution("test.txt", 'S', 'E', 1), 31);
+        assert_eq!(solution("test.txt", 'S', 'E'), 31);
     }
 
     #[test]
     fn test_solution2() {
-        assert_eq!(solution("test.txt", 'E', 'a', -1), 29);
+        assert_eq!(closest("test.txt", 'a'), 29);
+    }
+
+    #[test]
+    fn test_generated_map() {
+        let map = generate_map(60, 40, 1);
+        let path = search(&map, 0, 0, 'E').unwrap();
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert_eq!(path.last(), Some(&(59, 39)));
+        // The map is only read, so searching again gives the same path
+        assert_eq!(search(&map, 0, 0, 'E'), Some(path.clone()));
+        for w in path.windows(2) {
+            assert_eq!(w[0].0.abs_diff(w[1].0) + w[0].1.abs_diff(w[1].1), 1);
+        }
+        assert!(path.len() > 98);
     }
 
     #[test]
     fn test_real_solution() {
-        assert_le!(solution("input.txt", 'S', 'E', 1), 1003);
-        assert_ge!(solution("input.txt", 'S', 'E', 1), 455);
-        assert_eq!(solution("input.txt", 'S', 'E', 1), 456);
+        assert_le!(solution("input.txt", 'S', 'E'), 1003);
+        assert_ge!(solution("input.txt", 'S', 'E'), 455);
+        assert_eq!(solution("input.txt", 'S', 'E'), 456);
     }
 
     #[test]
     fn test_real_solution2() {
-        assert_ge!(solution("input.txt", 'E', 'a', -1), 16);
-        assert_le!(solution("input.txt", 'E', 'a', -1), 456);
-        assert_le!(solution("input.txt", 'E', 'a', -1), 455);
-        assert_eq!(solution("input.txt", 'E', 'a', -1), 454);
+        assert_ge!(closest("input.txt", 'a'), 16);
+        assert_le!(closest("input.txt", 'a'), 456);
+        assert_le!(closest("input.txt", 'a'), 455);
+        assert_eq!(closest("input.txt", 'a'), 454);
     }
 
     #[test]
     fn test_solution_larger() {
-        assert_ge!(solution("input.txt", 'E', 'a', -1), solution("input.txt", 'E', 'b', -1));
-        assert_ge!(solution("input.txt", 'E', 'c', -1), solution("input.txt", 'E', 'd', -1));
-        assert_ge!(solution("input.txt", 'E', 'f', -1), solution("input.txt", 'E', 'g', -1));
-        assert_ge!(solution("input.txt", 'E', 'h', -1), solution("input.txt", 'E', 'i', -1));
-        assert_ge!(solution("input.txt", 'E', 'k', -1), solution("input.txt", 'E', 'l', -1));
+        assert_ge!(closest("input.txt", 'a'), closest("input.txt", 'b'));
+        assert_ge!(closest("input.txt", 'c'), closest("input.txt", 'd'));
+        assert_ge!(closest("input.txt", 'f'), closest("input.txt", 'g'));
+        assert_ge!(closest("input.txt", 'h'), closest("input.txt", 'i'));
+        assert_ge!(closest("input.txt", 'k'), closest("input.txt", 'l'));
     }
 }
@@ -0,0 +1,174 @@
+use search::{Grid, Search};
+
+use crate::field::height;
+use crate::get_neighbours;
+
+/// Which steps between neighbouring cells are allowed, by the heights on both sides.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rule {
+    /// At most `k` levels up and any number down. The puzzle is `MaxClimb(1)`.
+    MaxClimb(u8),
+    /// At most `k` levels down and any number up
+    MaxDescent(u8),
+    /// At most `k` levels either way
+    Symmetric(u8),
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::MaxClimb(1)
+    }
+}
+
+impl Rule {
+    /// Parses `climb:K`, `descent:K` or `symmetric:K`.
+    pub fn parse(s: &str) -> Option<Rule> {
+        let (name, k) = s.split_once(':')?;
+        let k = k.parse().ok()?;
+        match name {
+            "climb" => Some(Rule::MaxClimb(k)),
+            "descent" => Some(Rule::MaxDescent(k)),
+            "symmetric" => Some(Rule::Symmetric(k)),
+            _ => None,
+        }
+    }
+
+    /// Whether a step from height `from` to height `to` is allowed.
+    pub fn allows(&self, from: u8, to: u8) -> bool {
+        let up = to as i32 - from as i32;
+        match self {
+            Rule::MaxClimb(k) => up <= *k as i32,
+            Rule::MaxDescent(k) => -up <= *k as i32,
+            Rule::Symmetric(k) => up.abs() <= *k as i32,
+        }
+    }
+
+    /// Whether a search can go from cell `a` to cell `b`. A negative `direction` searches
+    /// backwards from the end, so the step actually taken is from `b` to `a`.
+    pub fn can_travel(&self, a: u8, b: u8, direction: i32) -> bool {
+        let (a, b) = (height(a), height(b));
+        if direction > 0 {
+            self.allows(a, b)
+        } else {
+            self.allows(b, a)
+        }
+    }
+}
+
+/// What a step costs, by the heights on both sides.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Cost {
+    /// Every step costs 1, as in the puzzle
+    #[default]
+    Unit,
+    /// 1, plus `k` for every level climbed
+    Climb(u64),
+    /// 1, plus `k` for every level climbed or descended
+    Slope(u64),
+}
+
+impl Cost {
+    /// Parses `unit`, `climb:K` or `slope:K`.
+    pub fn parse(s: &str) -> Option<Cost> {
+        if s == "unit" {
+            return Some(Cost::Unit);
+        }
+        let (name, k) = s.split_once(':')?;
+        let k = k.parse().ok()?;
+        match name {
+            "climb" => Some(Cost::Climb(k)),
+            "slope" => Some(Cost::Slope(k)),
+            _ => None,
+        }
+    }
+
+    pub fn step(&self, from: u8, to: u8) -> u64 {
+        match self {
+            Cost::Unit => 1,
+            Cost::Climb(k) => 1 + k * to.saturating_sub(from) as u64,
+            Cost::Slope(k) => 1 + k * to.abs_diff(from) as u64,
+        }
+    }
+}
+
+/// The rule and the cost of moving across a height map.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Terrain {
+    pub rule: Rule,
+    pub cost: Cost,
+}
+
+impl Terrain {
+    /// The cheapest way from (x, y) to the closest cell marked `end`, with its cost.
+    pub fn cheapest(&self, map: &[Vec<u8>], x: usize, y: usize, end: char) -> Option<(u64, Vec<(usize, usize)>)> {
+        let neighbours = |&(x, y): &(usize, usize)| {
+            let from = height(map[y][x]);
+            get_neighbours(map, x, y, |a, b| self.rule.can_travel(a, b, 1))
+                .into_iter()
+                .map(move |(nx, ny)| ((nx, ny), self.cost.step(from, height(map[ny][nx]))))
+        };
+        let found = Search::with_store(Grid::new(map[0].len(), map.len()))
+            .dijkstra([(x, y)], neighbours, |&(x, y)| map[y][x] == end as u8);
+        Some((found.distance(found.goal.as_ref()?)?, found.path()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse_file;
+    use crate::field::height;
+    use crate::terrain::{Cost, Rule, Terrain};
+
+    fn map(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|r| r.bytes().collect()).collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(Rule::parse("climb:1"), Some(Rule::default()));
+        assert_eq!(Rule::parse("symmetric:2"), Some(Rule::Symmetric(2)));
+        assert_eq!(Rule::parse("jump:2"), None);
+        assert!(Rule::MaxClimb(2).allows(b'a', b'c') && !Rule::MaxClimb(2).allows(b'a', b'd'));
+        assert!(Rule::MaxClimb(0).allows(b'z', b'a'));
+        assert!(Rule::MaxDescent(1).allows(b'a', b'z') && !Rule::MaxDescent(1).allows(b'c', b'a'));
+        assert!(Rule::Symmetric(1).allows(b'c', b'b') && !Rule::Symmetric(1).allows(b'c', b'e'));
+        // The end is at the highest level
+        assert!(Rule::default().can_travel(b'y', b'E', 1) && !Rule::default().can_travel(b'x', b'E', 1));
+    }
+
+    #[test]
+    fn test_costs() {
+        assert_eq!(Cost::parse("unit"), Some(Cost::Unit));
+        assert_eq!(Cost::parse("slope:3"), Some(Cost::Slope(3)));
+        assert_eq!(Cost::parse("climb"), None);
+        assert_eq!(Cost::Climb(2).step(b'a', b'c'), 5);
+        assert_eq!(Cost::Climb(2).step(b'c', b'a'), 1);
+        assert_eq!(Cost::Slope(2).step(b'c', b'a'), 5);
+    }
+
+    #[test]
+    fn test_cheapest() {
+        let (map, s) = parse_file("test.txt", 'S');
+        let (x, y) = s.unwrap();
+        let (cost, path) = Terrain::default().cheapest(&map, x, y, 'E').unwrap();
+        assert_eq!((cost, path.len()), (31, 32));
+
+        // Every path climbs from a to z, so climbing at a cost adds at least 25 times that cost
+        let terrain = Terrain { cost: Cost::Climb(10), ..Terrain::default() };
+        let (cost, path) = terrain.cheapest(&map, x, y, 'E').unwrap();
+        let paid: u64 = path.windows(2).map(|w| terrain.cost.step(height(map[w[0].1][w[0].0]), height(map[w[1].1][w[1].0]))).sum();
+        assert_eq!(cost, paid);
+        assert!(cost >= 31 + 250);
+    }
+
+    #[test]
+    fn test_rule_changes_path() {
+        let map = map(&["Smamm", "aaaaE"]);
+        assert_eq!(Terrain::default().cheapest(&map, 0, 0, 'E'), None);
+        let steep = Terrain { rule: Rule::MaxClimb(25), ..Terrain::default() };
+        assert_eq!(steep.cheapest(&map, 0, 0, 'E').map(|(cost, _)| cost), Some(5));
+        // Going down into the dip of the top row and back up costs more than the bottom row
+        let slope = Terrain { rule: Rule::MaxClimb(25), cost: Cost::Slope(1) };
+        assert_eq!(slope.cheapest(&map, 0, 0, 'E'), Some((30, vec![(0, 0), (0, 1), (1, 1), (2, 1), (3, 1), (4, 1)])));
+    }
+}
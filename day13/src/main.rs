@@ -1,10 +1,14 @@
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::slice::ChunksExact;
 
 use crate::Element::{Digit, SubList};
+use crate::parser::ParseError;
+
+mod parser;
 
 static VERBOSE: u32 = 0;
 
@@ -13,177 +17,165 @@ fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader
     Ok(io::BufReader::new(file).lines())
 }
 
-#[derive(Debug)]
-#[derive(PartialEq)]
+#[derive(Debug, Clone)]
+#[derive(PartialEq, Eq)]
 enum Element {
     SubList(List),
     Digit(i32),
 }
 
-#[derive(Debug)]
-#[derive(PartialEq)]
+#[derive(Debug, Clone)]
+#[derive(PartialEq, Eq)]
 struct List {
     elements: Vec<Element>,
 }
 
-impl Eq for List {}
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Digit(d) => write!(f, "{}", d),
+            SubList(l) => write!(f, "{}", l),
+        }
+    }
+}
 
-impl PartialOrd<Self> for List {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+/// Prints the packet the way it is written in the puzzle, like `[1,[2,3],[]]`.
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, e) in self.elements.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", e)?;
+        }
+        write!(f, "]")
     }
 }
 
-impl Ord for List {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let c = compare_packets(self, other);
-        if c == 0 {
-            return Ordering::Equal;
+impl Element {
+    /// Compares like the puzzle does, turning a digit into a list when it meets one.
+    fn puzzle_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Digit(x), Digit(y)) => {
+                if VERBOSE >= 1 { println!("Comparing {} = {}", x, y) };
+                x.cmp(y)
+            }
+            (SubList(x), SubList(y)) => x.puzzle_cmp(y),
+            (SubList(x), Digit(y)) => x.puzzle_cmp(&List { elements: vec![Digit(*y)] }),
+            (Digit(x), SubList(y)) => List { elements: vec![Digit(*x)] }.puzzle_cmp(y),
         }
-        if c > 0 {
-            return Ordering::Greater;
+    }
+
+    /// Compares the way the elements are written, digits before lists.
+    fn structure_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Digit(x), Digit(y)) => x.cmp(y),
+            (SubList(x), SubList(y)) => x.structure_cmp(y),
+            (Digit(_), SubList(_)) => Ordering::Less,
+            (SubList(_), Digit(_)) => Ordering::Greater,
         }
-        Ordering::Less
     }
+}
 
-    fn max(self, other: Self) -> Self {
-        panic!("Not implemented");
+impl List {
+    /// The order of the puzzle: element by element, and a list running out first is smaller.
+    /// Some different packets are equal in it, like `[1]` and `[[1]]`.
+    fn puzzle_cmp(&self, other: &Self) -> Ordering {
+        self.elements.iter()
+            .zip(other.elements.iter())
+            .map(|(a, b)| a.puzzle_cmp(b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| self.elements.len().cmp(&other.elements.len()))
     }
 
-    fn min(self, other: Self) -> Self {
-        panic!("Not implemented");
+    fn structure_cmp(&self, other: &Self) -> Ordering {
+        self.elements.iter()
+            .zip(other.elements.iter())
+            .map(|(a, b)| a.structure_cmp(b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| self.elements.len().cmp(&other.elements.len()))
     }
+}
 
-    fn clamp(self, min: Self, max: Self) -> Self {
-        panic!("Not implemented");
+/// Ordered like packets, see `List`.
+impl Ord for Element {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.puzzle_cmp(other).then_with(|| self.structure_cmp(other))
     }
 }
 
-fn parse_string(input: &str) -> (usize, List) {
-    if VERBOSE >= 2 { println!("Sublist found {}", input); }
-    let mut l = List { elements: Vec::new() };
-    let mut buf: String = String::from("");
-
-    let mut i = 1;
-    let chars: Vec<char> = input.chars().collect();
-    while i < input.len() {
-        let c = chars[i];
-        if VERBOSE >= 2 { println!("Parsing {}", c); }
-        match c {
-            '[' => {
-                let (pos, sublist) = parse_string(&input[i..]);
-                i += pos;
-                l.elements.push(SubList(sublist));
-            }
-            ']' => {
-                if !buf.is_empty() {
-                    l.elements.push(Digit(buf.parse::<i32>().unwrap()));
-                }
-                return (i, l);
-            }
-            ',' => {
-                if !buf.is_empty() {
-                    l.elements.push(Digit(buf.parse::<i32>().unwrap()));
-                    buf = String::from("");
-                }
-            }
-            _ => { buf.push(c); }
-        }
-        i += 1;
+impl PartialOrd<Self> for Element {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
-    panic!("Unexpected end of string");
 }
 
-fn is_lists_in_order(list_a: &List, list_b: &List) -> bool {
-    if compare_packets(list_a, list_b) > 0 {
-        return true;
+/// The order of the puzzle, with packets it finds equal ordered by how they are written so
+/// that only identical packets are equal.
+impl Ord for List {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.puzzle_cmp(other).then_with(|| self.structure_cmp(other))
     }
-    false
 }
 
-fn compare_packets(list_a: &List, list_b: &List) -> i8 {
-    let mut iter_b = list_b.elements.iter();
-    for a in list_a.elements.iter() {
-        let b = iter_b.next();
-        if b.is_none() {
-            return -1;
-        }
-        let b = b.unwrap();
-        match (a, b) {
-            (Digit(x), Digit(y)) => {
-                if VERBOSE >= 1 { println!("Comparing {} = {}", x, y) };
-                if x < y {
-                    return 1;
-                }
-                if x > y {
-                    return -1;
-                }
-            }
-            (SubList(x), SubList(y)) => {
-                let r = compare_packets(x, y);
-                if r != 0 {
-                    return r;
-                }
-            }
-            (SubList(x), Digit(y)) => {
-                let r = compare_packets(x, &List { elements: vec![Digit(*y)] });
-                if r != 0 {
-                    return r;
-                }
-            }
-            (Digit(x), SubList(y)) => {
-                let r = compare_packets(&List { elements: vec![Digit(*x)] }, y);
-                if r != 0 {
-                    return r;
-                }
-            }
-        }
+impl PartialOrd<Self> for List {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
-    let b = iter_b.next();
-    if b.is_none() {
-        return 0;
+}
+
+/// Reads one packet per line, skipping blank lines. Errors carry the line number.
+fn parse_packets(text: &str) -> Result<Vec<List>, (usize, ParseError)> {
+    let mut packets = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if !line.is_empty() {
+            packets.push(line.parse::<List>().map_err(|e| (i + 1, e))?);
+        }
     }
-    1
+    Ok(packets)
+}
+
+fn parse_file(file: &str) -> Result<Vec<List>, (usize, ParseError)> {
+    let lines: Vec<String> = read_lines(file).map(|lines| lines.map_while(Result::ok).collect()).unwrap_or_default();
+    parse_packets(&lines.join("\n"))
+}
+
+fn load(file: &str) -> Vec<List> {
+    parse_file(file).unwrap_or_else(|(line, e)| panic!("{} line {}: {}", file, line, e))
+}
+
+fn is_lists_in_order(list_a: &List, list_b: &List) -> bool {
+    list_a.puzzle_cmp(list_b) == Ordering::Less
+}
+
+/// The packets two by two, or `None` if the last one has no pair.
+fn pairs(packets: &[List]) -> Option<ChunksExact<'_, List>> {
+    packets.len().is_multiple_of(2).then(|| packets.chunks_exact(2))
 }
 
 fn solution(file: &str) -> usize {
-    let mut buf = Vec::new();
+    let packets = load(file);
+    let pairs = pairs(&packets).unwrap_or_else(|| panic!("{}: {} packets cannot be paired", file, packets.len()));
     let mut s = 0;
-    if let Ok(lines) = read_lines(file) {
-        for (i, line) in lines.enumerate() {
-            if let Ok(l) = line {
-                buf.push(l);
-                if i % 3 == 2 {
-                    if is_lists_in_order(&parse_string(&buf[0]).1, &parse_string(&buf[1]).1) {
-                        if VERBOSE >= 1 { println!("{}", (i + 1) / 3) }
-                        s += (i + 1) / 3;
-                    };
-                    buf = Vec::new();
-                }
-            }
+    for (i, pair) in pairs.enumerate() {
+        if is_lists_in_order(&pair[0], &pair[1]) {
+            if VERBOSE >= 1 { println!("{}", i + 1) }
+            s += i + 1;
         }
     }
     s
 }
 
 fn solution2(file: &str) -> usize {
-    let mut buf = BinaryHeap::new();
-    buf.push(parse_string("[[2]]").1);
-    buf.push(parse_string("[[6]]").1);
+    let dividers: Vec<List> = ["[[2]]", "[[6]]"].iter().map(|d| d.parse().unwrap()).collect();
+    let mut packets = load(file);
+    packets.extend(dividers.iter().cloned());
+    packets.sort();
     let mut s = 1;
-    if let Ok(lines) = read_lines(file) {
-        for (i, line) in lines.enumerate() {
-            if let Ok(l) = line {
-                if i % 3 != 2 {
-                    buf.push(parse_string(&l).1);
-                }
-            }
-        }
-    }
-    for (i, x) in buf.into_sorted_vec().iter().rev().enumerate() {
-        if VERBOSE >= 1 { println!("{:?}", x); }
-        if x == &parse_string("[[2]]").1 ||
-            x == &parse_string("[[6]]").1 {
+    for (i, x) in packets.iter().enumerate() {
+        if VERBOSE >= 1 { println!("{}", x); }
+        if dividers.contains(x) {
             s *= i + 1;
         }
     }
@@ -196,13 +188,19 @@ fn main() {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
-    use std::cmp::Ordering;
-    use std::collections::BinaryHeap;
+    use std::cmp::{max, min, Ordering, Reverse};
+    use std::collections::{BinaryHeap, BTreeSet};
 
     use more_asserts::{assert_ge, assert_le};
 
-    use crate::{Element::*, is_lists_in_order, List, parse_string, solution, solution2};
+    use crate::{Element::*, is_lists_in_order, List, pairs, parse_file, parse_packets, solution, solution2};
+    use crate::parser::ParseError;
+
+    fn packet(s: &str) -> List {
+        s.parse().unwrap()
+    }
 
     #[test]
     fn test_part1_test_input() {
@@ -233,7 +231,7 @@ mod test {
         l2.elements.push(Digit(20));
         l2.elements.push(Digit(30));
 
-        let mut l3 = List { elements: Vec::new() };
+        let l3 = List { elements: Vec::new() };
         l2.elements.push(SubList(l3));
 
         l.elements.push(SubList(l2));
@@ -242,128 +240,166 @@ mod test {
 
     #[test]
     fn test_parse_string() {
-        assert_eq!(parse_string("[]").1, List { elements: Vec::new() });
-        assert_eq!(parse_string("[1]").1, List { elements: vec![Digit(1)] });
-        assert_eq!(parse_string("[100]").1, List { elements: vec![Digit(100)] });
-        assert_eq!(parse_string("[1,2]").1, List { elements: vec![Digit(1), Digit(2)] });
-        assert_eq!(parse_string("[[]]").1, List { elements: vec![SubList(List { elements: vec![] })] });
-        assert_eq!(parse_string("[[1]]").1, List { elements: vec![SubList(List { elements: vec![Digit(1)] })] });
-        assert_eq!(parse_string("[1,[]]").1, List { elements: vec![Digit(1), SubList(List { elements: vec![] })] });
+        assert_eq!(packet("[]"), List { elements: Vec::new() });
+        assert_eq!(packet("[1]"), List { elements: vec![Digit(1)] });
+        assert_eq!(packet("[100]"), List { elements: vec![Digit(100)] });
+        assert_eq!(packet("[1,2]"), List { elements: vec![Digit(1), Digit(2)] });
+        assert_eq!(packet("[[]]"), List { elements: vec![SubList(List { elements: vec![] })] });
+        assert_eq!(packet("[[1]]"), List { elements: vec![SubList(List { elements: vec![Digit(1)] })] });
+        assert_eq!(packet("[1,[]]"), List { elements: vec![Digit(1), SubList(List { elements: vec![] })] });
     }
 
     #[test]
-    fn test_is_lists_in_order() {
-        assert_eq!(is_lists_in_order(&parse_string("[1,1,3,1,1]").1, &parse_string("[1,1,5,1,1]").1), true);
-        assert_eq!(is_lists_in_order(&parse_string("[[1],[2,3,4]]").1, &parse_string("[[1],4]").1), true);
-        assert_eq!(is_lists_in_order(&parse_string("[9]").1, &parse_string("[[8,7,6]]").1), false);
-        assert_eq!(is_lists_in_order(&parse_string("[[4,4],4,4]").1, &parse_string("[[4,4],4,4,4]").1), true);
-        assert_eq!(is_lists_in_order(&parse_string("[7,7,7,7]").1, &parse_string("[7,7,7]").1), false);
-        assert_eq!(is_lists_in_order(&parse_string("[]").1, &parse_string("[3]").1), true);
-        assert_eq!(is_lists_in_order(&parse_string("[[[]]]").1, &parse_string("[[]]").1), false);
-        assert_eq!(is_lists_in_order(&parse_string("[1,[2,[3,[4,[5,6,7]]]],8,9]").1, &parse_string("[1,[2,[3,[4,[5,6,0]]]],8,9]").1), false);
+    fn test_parse_file() {
+        assert_eq!(parse_file("test.txt").unwrap().len(), 16);
+        assert_eq!(parse_packets("[1]\n[2]\n\n[[3]\n").err(), Some((4, ParseError::Unclosed(0))));
+    }
 
-        assert_eq!(is_lists_in_order(&parse_string("[1,[1],2]").1, &parse_string("[1,[1],2]").1), false);
-        assert_eq!(is_lists_in_order(&parse_string("[1,[1],2]").1, &parse_string("[1,[1],1]").1), false);
-        assert_eq!(is_lists_in_order(&parse_string("[1,[1,1],2]").1, &parse_string("[1,[1],1]").1), false);
+    #[test]
+    fn test_pairs() {
+        let packets = parse_packets("[1]\n[2]\n\n[3]\n").unwrap();
+        assert!(pairs(&packets).is_none());
+        assert_eq!(pairs(&packets[..2]).map(|p| p.count()), Some(1));
+    }
+
+    #[test]
+    fn test_is_lists_in_order() {
+        assert_eq!(is_lists_in_order(&packet("[1,1,3,1,1]"), &packet("[1,1,5,1,1]")), true);
+        assert_eq!(is_lists_in_order(&packet("[[1],[2,3,4]]"), &packet("[[1],4]")), true);
+        assert_eq!(is_lists_in_order(&packet("[9]"), &packet("[[8,7,6]]")), false);
+        assert_eq!(is_lists_in_order(&packet("[[4,4],4,4]"), &packet("[[4,4],4,4,4]")), true);
+        assert_eq!(is_lists_in_order(&packet("[7,7,7,7]"), &packet("[7,7,7]")), false);
+        assert_eq!(is_lists_in_order(&packet("[]"), &packet("[3]")), true);
+        assert_eq!(is_lists_in_order(&packet("[[[]]]"), &packet("[[]]")), false);
+        assert_eq!(is_lists_in_order(&packet("[1,[2,[3,[4,[5,6,7]]]],8,9]"), &packet("[1,[2,[3,[4,[5,6,0]]]],8,9]")), false);
+
+        assert_eq!(is_lists_in_order(&packet("[1,[1],2]"), &packet("[1,[1],2]")), false);
+        assert_eq!(is_lists_in_order(&packet("[1,[1],2]"), &packet("[1,[1],1]")), false);
+        assert_eq!(is_lists_in_order(&packet("[1,[1,1],2]"), &packet("[1,[1],1]")), false);
     }
 
     #[test]
     fn test_regression() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[[[5,[8,5]],[9,2]],[[[4,9],[3,1],[2,7,5],[2,9,2]],0],[[2,10,2,[],[4,4,4]]],[[7,[1,2,5],[],9],[8,3,[3,8,0,1,10],0,5],1,7],[[[8,0,6,2],[],4,[10]],3,[],4,8]]").1,
-            &parse_string("[[[[1,8,4,2,4],[0,2,0],5],[7,[4,7,10],[]],[[4,0],2],[8,[2,0,10,4],[7,6]]],[],[1,[[1,4,9,8,6],4]]]").1,
+            &packet("[[[5,[8,5]],[9,2]],[[[4,9],[3,1],[2,7,5],[2,9,2]],0],[[2,10,2,[],[4,4,4]]],[[7,[1,2,5],[],9],[8,3,[3,8,0,1,10],0,5],1,7],[[[8,0,6,2],[],4,[10]],3,[],4,8]]"),
+            &packet("[[[[1,8,4,2,4],[0,2,0],5],[7,[4,7,10],[]],[[4,0],2],[8,[2,0,10,4],[7,6]]],[],[1,[[1,4,9,8,6],4]]]"),
         ), false);
     }
 
     #[test]
     fn test_regression_empty_list() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[]").1,
-            &parse_string("[]").1,
+            &packet("[]"),
+            &packet("[]"),
         ), false);
     }
 
     #[test]
     fn test_regression2() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[[]]").1,
-            &parse_string("[]").1,
+            &packet("[[]]"),
+            &packet("[]"),
         ), false);
     }
 
     #[test]
     fn test_regression3() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[]").1,
-            &parse_string("[[]]").1,
+            &packet("[]"),
+            &packet("[[]]"),
         ), true);
     }
 
     #[test]
     fn test_regression4() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[1]").1,
-            &parse_string("[[]]").1,
+            &packet("[1]"),
+            &packet("[[]]"),
         ), false);
     }
 
     #[test]
     fn test_regression5() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[[]]").1,
-            &parse_string("[1]").1,
+            &packet("[[]]"),
+            &packet("[1]"),
         ), true);
     }
 
     #[test]
     fn test_regression6() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[1,2]").1,
-            &parse_string("[1]").1,
+            &packet("[1,2]"),
+            &packet("[1]"),
         ), false);
     }
 
     #[test]
     fn test_regression7() {
         assert_eq!(is_lists_in_order(
-            &parse_string("[1]").1,
-            &parse_string("[1,2]").1,
+            &packet("[1]"),
+            &packet("[1,2]"),
         ), true);
     }
 
     #[test]
     fn test_ord() {
-        assert_eq!(parse_string("[1]").1.cmp(&parse_string("[1,2]").1),
-                   Ordering::Greater);
-        assert_eq!(parse_string("[1,2]").1.cmp(&parse_string("[1]").1),
+        assert_eq!(packet("[1]").cmp(&packet("[1,2]")),
                    Ordering::Less);
-        assert_eq!(parse_string("[1,2]").1.cmp(&parse_string("[1,2]").1),
+        assert_eq!(packet("[1,2]").cmp(&packet("[1]")),
+                   Ordering::Greater);
+        assert_eq!(packet("[1,2]").cmp(&packet("[1,2]")),
                    Ordering::Equal);
+        // Equal for the puzzle, but only identical packets are equal
+        assert_eq!(packet("[1]").cmp(&packet("[[1]]")),
+                   Ordering::Less);
+        assert!(packet("[1,5]") > packet("[[1],3]"));
+        assert_eq!(max(packet("[2]"), packet("[1,9]")), packet("[2]"));
+        assert_eq!(packet("[5]").clamp(packet("[1]"), packet("[3]")), packet("[3]"));
+    }
+
+    #[test]
+    fn test_element_ord() {
+        assert!(Digit(3) < Digit(4));
+        assert!(Digit(3) < SubList(packet("[4]")));
+        assert!(Digit(3) > SubList(packet("[[2],9]")));
+        assert!(SubList(packet("[]")) < Digit(0));
+        assert_eq!(min(Digit(7), SubList(packet("[[7]]"))), Digit(7));
+    }
+
+    #[test]
+    fn test_btree_set() {
+        let packets: BTreeSet<List> = ["[1,[1]]", "[]", "[[1]]", "[1]", "[[]]", "[1,[1]]", "[0,9]"]
+            .iter()
+            .map(|p| packet(p))
+            .collect();
+        let sorted: Vec<List> = ["[]", "[[]]", "[0,9]", "[1]", "[[1]]", "[1,[1]]"].iter().map(|p| packet(p)).collect();
+        assert_eq!(packets.into_iter().collect::<Vec<List>>(), sorted);
     }
 
     #[test]
     fn test_min_heap() {
         let mut heap = BinaryHeap::new();
-        heap.push(parse_string("[1,2,3]").1);
-        heap.push(parse_string("[1]").1);
-        heap.push(parse_string("[1,2]").1);
+        heap.push(Reverse(packet("[1,2,3]")));
+        heap.push(Reverse(packet("[1]")));
+        heap.push(Reverse(packet("[1,2]")));
 
-        assert_eq!(heap.pop().unwrap(), parse_string("[1]").1);
-        assert_eq!(heap.pop().unwrap(), parse_string("[1,2]").1);
-        assert_eq!(heap.pop().unwrap(), parse_string("[1,2,3]").1);
+        assert_eq!(heap.pop().unwrap().0, packet("[1]"));
+        assert_eq!(heap.pop().unwrap().0, packet("[1,2]"));
+        assert_eq!(heap.pop().unwrap().0, packet("[1,2,3]"));
     }
 
     #[test]
     fn test_min_heap2() {
         let mut heap = BinaryHeap::new();
-        heap.push(parse_string("[]").1);
-        heap.push(parse_string("[[]]").1);
-        heap.push(parse_string("[1,1,5,1,1]").1);
-        heap.push(parse_string("[1,1,3,1,1]").1);
-
-        assert_eq!(heap.pop().unwrap(), parse_string("[]").1);
-        assert_eq!(heap.pop().unwrap(), parse_string("[[]]").1);
-        assert_eq!(heap.pop().unwrap(), parse_string("[1,1,3,1,1]").1);
-        assert_eq!(heap.pop().unwrap(), parse_string("[1,1,5,1,1]").1);
+        heap.push(Reverse(packet("[]")));
+        heap.push(Reverse(packet("[[]]")));
+        heap.push(Reverse(packet("[1,1,5,1,1]")));
+        heap.push(Reverse(packet("[1,1,3,1,1]")));
+
+        assert_eq!(heap.pop().unwrap().0, packet("[]"));
+        assert_eq!(heap.pop().unwrap().0, packet("[[]]"));
+        assert_eq!(heap.pop().unwrap().0, packet("[1,1,3,1,1]"));
+        assert_eq!(heap.pop().unwrap().0, packet("[1,1,5,1,1]"));
     }
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Element::{Digit, SubList};
+use crate::{Element, List};
+
+/// Why a packet could not be read, with the position of the character it failed on.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    ExpectedList(usize),
+    /// The list opened at this position is never closed
+    Unclosed(usize),
+    UnexpectedCharacter(usize, char),
+    MissingElement(usize),
+    InvalidNumber(usize, String),
+    TrailingCharacters(usize),
+    /// The list opened at this position is nested deeper than `MAX_DEPTH`
+    TooDeep(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty packet"),
+            ParseError::ExpectedList(p) => write!(f, "expected a list at {}", p),
+            ParseError::Unclosed(p) => write!(f, "the list opened at {} is never closed", p),
+            ParseError::UnexpectedCharacter(p, c) => write!(f, "unexpected {:?} at {}", c, p),
+            ParseError::MissingElement(p) => write!(f, "missing element at {}", p),
+            ParseError::InvalidNumber(p, s) => write!(f, "invalid number {} at {}", s, p),
+            ParseError::TrailingCharacters(p) => write!(f, "characters after the end of the packet at {}", p),
+            ParseError::TooDeep(p) => write!(f, "the list opened at {} is nested more than {} deep", p, MAX_DEPTH),
+        }
+    }
+}
+
+/// How deep lists can be nested, which keeps the recursion of the parser off the end of the stack.
+pub const MAX_DEPTH: usize = 256;
+
+/// Recursive descent over the characters, one call per list.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// A list nested in `depth` others.
+    fn list(&mut self, depth: usize) -> Result<List, ParseError> {
+        let open = self.pos;
+        if self.peek() != Some('[') {
+            return Err(ParseError::ExpectedList(open));
+        }
+        if depth >= MAX_DEPTH {
+            return Err(ParseError::TooDeep(open));
+        }
+        self.pos += 1;
+        let mut l = List { elements: Vec::new() };
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(l);
+        }
+        loop {
+            l.elements.push(self.element(open, depth)?);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(l);
+                }
+                Some(c) => return Err(ParseError::UnexpectedCharacter(self.pos, c)),
+                None => return Err(ParseError::Unclosed(open)),
+            }
+        }
+    }
+
+    /// An element of the list opened at `open`, itself nested in `depth` others.
+    fn element(&mut self, open: usize, depth: usize) -> Result<Element, ParseError> {
+        match self.peek() {
+            Some('[') => Ok(SubList(self.list(depth + 1)?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let n: String = self.chars[start..self.pos].iter().collect();
+                n.parse().map(Digit).map_err(|_| ParseError::InvalidNumber(start, n))
+            }
+            Some(',') | Some(']') => Err(ParseError::MissingElement(self.pos)),
+            Some(c) => Err(ParseError::UnexpectedCharacter(self.pos, c)),
+            None => Err(ParseError::Unclosed(open)),
+        }
+    }
+}
+
+/// Reads a packet like `[1,[2,3],[]]`. Nothing but digits, a minus sign, brackets and commas
+/// is allowed, not even spaces.
+impl FromStr for List {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<List, ParseError> {
+        if s.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        let mut parser = Parser { chars: s.chars().collect(), pos: 0 };
+        let l = parser.list(0)?;
+        if parser.pos < parser.chars.len() {
+            return Err(ParseError::TrailingCharacters(parser.pos));
+        }
+        Ok(l)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use quickcheck::{quickcheck, Arbitrary, Gen};
+
+    use crate::Element::{Digit, SubList};
+    use crate::List;
+    use crate::parser::{MAX_DEPTH, ParseError};
+
+    fn arbitrary_list(g: &mut Gen, depth: usize) -> List {
+        let len = usize::arbitrary(g) % 5;
+        let elements = (0..len)
+            .map(|_| if depth > 0 && bool::arbitrary(g) { SubList(arbitrary_list(g, depth - 1)) } else { Digit(i32::arbitrary(g)) })
+            .collect();
+        List { elements }
+    }
+
+    impl Arbitrary for List {
+        fn arbitrary(g: &mut Gen) -> List {
+            arbitrary_list(g, 4)
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = List>> {
+            // Every packet with one element less
+            let packet = self.clone();
+            Box::new((0..packet.elements.len()).map(move |i| {
+                let mut smaller = packet.clone();
+                smaller.elements.remove(i);
+                smaller
+            }))
+        }
+    }
+
+    quickcheck! {
+        fn prop_print_parse(packet: List) -> bool {
+            packet.to_string().parse::<List>() == Ok(packet)
+        }
+
+        fn prop_parse_print(packet: List) -> bool {
+            let printed = packet.to_string();
+            printed.parse::<List>().map(|p| p.to_string()) == Ok(printed)
+        }
+
+        fn prop_truncated(packet: List, cut: usize) -> bool {
+            let printed = packet.to_string();
+            printed[..cut % printed.len()].parse::<List>().is_err()
+        }
+
+        fn prop_never_panics(s: String) -> bool {
+            let _ = s.parse::<List>();
+            true
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        for s in ["[]", "[1]", "[[]]", "[1,[2,[3,[4,[5,6,7]]]],8,9]", "[-3,10]"] {
+            assert_eq!(s.parse::<List>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s: &str| s.parse::<List>().err();
+        assert_eq!(error(""), Some(ParseError::Empty));
+        assert_eq!(error("1"), Some(ParseError::ExpectedList(0)));
+        assert_eq!(error("["), Some(ParseError::Unclosed(0)));
+        assert_eq!(error("[1,[2,3]"), Some(ParseError::Unclosed(0)));
+        assert_eq!(error("[[1,2]"), Some(ParseError::Unclosed(0)));
+        assert_eq!(error("[1,[2,"), Some(ParseError::Unclosed(3)));
+        assert_eq!(error("[1]]"), Some(ParseError::TrailingCharacters(3)));
+        assert_eq!(error("[1][2]"), Some(ParseError::TrailingCharacters(3)));
+        assert_eq!(error("[1,,2]"), Some(ParseError::MissingElement(3)));
+        assert_eq!(error("[1,]"), Some(ParseError::MissingElement(3)));
+        assert_eq!(error("[,1]"), Some(ParseError::MissingElement(1)));
+        assert_eq!(error("[1, 2]"), Some(ParseError::UnexpectedCharacter(3, ' ')));
+        assert_eq!(error("[1a]"), Some(ParseError::UnexpectedCharacter(2, 'a')));
+        assert_eq!(error("[1[2]]"), Some(ParseError::UnexpectedCharacter(2, '[')));
+        assert_eq!(error("[-]"), Some(ParseError::InvalidNumber(1, String::from("-"))));
+        assert_eq!(error("[99999999999]"), Some(ParseError::InvalidNumber(1, String::from("99999999999"))));
+        assert_eq!(ParseError::Unclosed(3).to_string(), "the list opened at 3 is never closed");
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(nested(MAX_DEPTH).parse::<List>().is_ok());
+        assert_eq!(nested(MAX_DEPTH + 1).parse::<List>().err(), Some(ParseError::TooDeep(MAX_DEPTH)));
+        assert_eq!("[".repeat(100_000).parse::<List>().err(), Some(ParseError::TooDeep(MAX_DEPTH)));
+    }
+}
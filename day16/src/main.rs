@@ -1,10 +1,11 @@
-use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 use lazy_static::lazy_static;
 use regex::Regex;
 use itertools::Itertools;
+use search::bfs;
 
 static VERBOSE: i32 = 1;
 
@@ -65,8 +66,8 @@ impl Room {
         let cap = RE.captures_iter(input).next().unwrap();
 
         let mut n = Vec::new();
-        let mut s = cap[3].split(", ");
-        while let Some(r) = s.next() {
+        let s = cap[3].split(", ");
+        for r in s {
             n.push(String::from(r));
         }
 
@@ -88,7 +89,7 @@ fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader
 fn parse_file(file: &str) -> HashMap<String, Room> {
     let mut rooms = HashMap::new();
     if let Ok(lines) = read_lines(file) {
-        for line in lines.flatten() {
+        for line in lines.map_while(Result::ok) {
             let s = Room::from_string(&line);
             rooms.insert(s.name.clone(), s);
         }
@@ -97,23 +98,10 @@ fn parse_file(file: &str) -> HashMap<String, Room> {
 }
 
 fn calculate_distance(rooms: &HashMap<String, Room>, from: String, to: String) -> i32 {
-    let mut n = VecDeque::new();
-    let mut v = BTreeSet::new();
-    n.push_back((0, from));
-
-    while let Some((d, a)) = n.pop_front() {
-        if a == to {
-            return d;
-        }
-        for new_neighbour in rooms.get(&a).unwrap().neighbours.iter() {
-            if let None = v.get(new_neighbour) {
-                n.push_back((d+1, new_neighbour.clone()));
-                v.insert(new_neighbour);
-            }
-        }
-    }
-
-    return -1;
+    let neighbours = |a: &&str| rooms.get(*a).unwrap().neighbours.iter().map(String::as_str);
+    bfs([from.as_str()], neighbours, |a| *a == to)
+        .distance(&to.as_str())
+        .map_or(-1, |d| d as i32)
 }
 
 fn calculate_pressure(rooms: &HashMap<String, Room>, valves: Vec<&String>) -> i32 {
@@ -148,7 +136,7 @@ fn solution(file: &str) -> i32 {
 
     let mut max_pressure = 0;
     let mut i = 0;
-    let mut iter = valves.iter().permutations(valves.len());
+    let iter = valves.iter().permutations(valves.len());
     for v in iter {
         if VERBOSE >= 2 { println!("{:?}", v); }
         if VERBOSE == 1 {
@@ -177,10 +165,21 @@ fn main() {
 
 #[cfg(test)]
 mod test {
-    use crate::solution;
+    use crate::{calculate_distance, parse_file, solution};
 
     #[test]
     fn test_part1_test_input() {
         assert_eq!(solution("test.txt"), 1651);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_distance() {
+        let rooms = parse_file("test.txt");
+        let distance = |from: &str, to: &str| calculate_distance(&rooms, String::from(from), String::from(to));
+        assert_eq!(distance("AA", "AA"), 0);
+        assert_eq!(distance("AA", "JJ"), 2);
+        assert_eq!(distance("AA", "HH"), 5);
+        assert_eq!(distance("HH", "JJ"), 7);
+        assert_eq!(distance("AA", "ZZ"), -1);
+    }
+}
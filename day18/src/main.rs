@@ -3,6 +3,8 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+use search::bfs;
+
 fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>> {
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
@@ -20,7 +22,7 @@ fn parse_string(input: &str) -> (i32, i32, i32) {
 fn parse_file(file: &str) -> BTreeMap<(i32, i32, i32), i32> {
     let mut lava = BTreeMap::new();
     if let Ok(lines) = read_lines(file) {
-        for line in lines.flatten() {
+        for line in lines.map_while(Result::ok) {
             lava.insert(parse_string(&line), 1);
         }
     }
@@ -32,46 +34,43 @@ fn solution(file: &str) -> i32 {
     let lava = parse_file(file);
 
     for ((x, y, z), _) in lava.iter() {
-        if lava.get(&(*x + 1, *y, *z)).is_none() {
+        if !lava.contains_key(&(*x + 1, *y, *z)) {
             surfaces += 1;
         }
-        if lava.get(&(*x - 1, *y, *z)).is_none() {
+        if !lava.contains_key(&(*x - 1, *y, *z)) {
             surfaces += 1;
         }
-        if lava.get(&(*x, *y + 1, *z)).is_none() {
+        if !lava.contains_key(&(*x, *y + 1, *z)) {
             surfaces += 1;
         }
-        if lava.get(&(*x, *y - 1, *z)).is_none() {
+        if !lava.contains_key(&(*x, *y - 1, *z)) {
             surfaces += 1;
         }
-        if lava.get(&(*x, *y, *z + 1)).is_none() {
+        if !lava.contains_key(&(*x, *y, *z + 1)) {
             surfaces += 1;
         }
-        if lava.get(&(*x, *y, *z - 1)).is_none() {
+        if !lava.contains_key(&(*x, *y, *z - 1)) {
             surfaces += 1;
         }
     }
     surfaces
 }
 
+/// Marks with 2 every cell the steam reaches from (0, 0, 0) without going through lava or
+/// leaving the -5..=25 box around the droplet.
 fn fill(mut lava: BTreeMap<(i32, i32, i32), i32>) -> BTreeMap<(i32, i32, i32), i32> {
-    let mut steam_expansion = Vec::new();
-    steam_expansion.push((0, 0, 0));
-
-    while let Some((x, y, z)) = steam_expansion.pop() {
-        if x >= -5 && x <= 25 &&
-            y >= -5 && y <= 25 &&
-            z >= -5 && z <= 25 {
-            if let None = lava.get(&(x, y, z)) {
-                lava.insert((x, y, z), 2);
-                steam_expansion.push((x + 1, y, z));
-                steam_expansion.push((x - 1, y, z));
-                steam_expansion.push((x, y + 1, z));
-                steam_expansion.push((x, y - 1, z));
-                steam_expansion.push((x, y, z + 1));
-                steam_expansion.push((x, y, z - 1));
-            }
-        }
+    if lava.contains_key(&(0, 0, 0)) {
+        return lava;
+    }
+    let neighbours = |&(x, y, z): &(i32, i32, i32)| {
+        [(x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y - 1, z), (x, y, z + 1), (x, y, z - 1)]
+            .into_iter()
+            .filter(|(x, y, z)| [x, y, z].iter().all(|c| (-5..=25).contains(*c)))
+            .filter(|cell| !lava.contains_key(cell))
+    };
+    let steam: Vec<(i32, i32, i32)> = bfs([(0, 0, 0)], neighbours, |_| false).distances().map(|(cell, _)| cell).collect();
+    for cell in steam {
+        lava.insert(cell, 2);
     }
     lava
 }
@@ -124,3 +123,25 @@ fn main() {
     // 2057 wrong
     println!("Solution: {}", solution2("input.txt"));
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{fill, parse_file, solution, solution2};
+
+    #[test]
+    fn test_solution() {
+        assert_eq!(solution("test.txt"), 64);
+        assert_eq!(solution2("test.txt"), 58);
+    }
+
+    #[test]
+    fn test_fill() {
+        let lava = parse_file("test.txt");
+        let map = fill(lava.clone());
+        // The air pocket inside the droplet stays empty
+        assert_eq!(map.get(&(2, 2, 5)), None);
+        assert_eq!(map.get(&(0, 0, 0)), Some(&2));
+        assert_eq!(map.get(&(2, 2, 2)), Some(&1));
+        assert_eq!(map.len(), 31 * 31 * 31 - 1);
+    }
+}
@@ -1,87 +1,385 @@
+use std::collections::BTreeMap;
+use std::{env, fmt, fs};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+use crate::shell::Shell;
+
+mod report;
+mod shell;
+
+static TOTAL_DISK_SPACE: u64 = 70000000;
+static REQUIRED_DISK_SPACE: u64 = 30000000;
+
 fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>> {
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
 
+#[derive(Debug, PartialEq)]
 enum Command {
     ChangeDirectory(String),
     ListDirectory,
     Directory(String),
-    File(String, i32),
+    File(String, u64),
+    Unknown(String),
+    /// A line that is neither a command nor a line of `ls` output
+    Malformed(String),
 }
 
 fn parse_string(l: String) -> Command {
-    let mut token = l.split(" ");
-    return match token.next().unwrap() {
+    let mut token = l.split(' ');
+    match token.next().unwrap() {
         "$" => {
-            match token.next().unwrap() {
-                "cd" => {
-                    Command::ChangeDirectory(token.next().unwrap().to_string())
+            match token.next() {
+                Some("cd") => {
+                    match token.next() {
+                        Some(dir) if !dir.is_empty() => Command::ChangeDirectory(dir.to_string()),
+                        _ => Command::Malformed(l.clone()),
+                    }
                 }
-                "ls" => {
+                Some("ls") => {
                     Command::ListDirectory
                 }
-                _ => {
-                    Command::ListDirectory
+                None | Some("") => {
+                    Command::Malformed(l.clone())
+                }
+                Some(unknown) => {
+                    Command::Unknown(std::iter::once(unknown).chain(token).collect::<Vec<_>>().join(" "))
                 }
             }
         }
         "dir" => {
-            Command::Directory(String::from(token.next().unwrap()))
+            match token.next() {
+                Some(name) if !name.is_empty() => Command::Directory(String::from(name)),
+                _ => Command::Malformed(l.clone()),
+            }
         }
         size => {
-            Command::File(String::from(token.next().unwrap()), size.parse::<i32>().unwrap())
+            match (token.next(), size.parse::<u64>()) {
+                (Some(name), Ok(size)) if !name.is_empty() => Command::File(String::from(name), size),
+                _ => Command::Malformed(l.clone()),
+            }
         }
     }
 }
 
-fn solution() -> i32 {
-    let mut solution = 0;
-    let mut directory_stack = Vec::new();
-    let mut directory_size = 0;
-    if let Ok(lines) = read_lines("input.txt") {
-        for line in lines {
-            if let Ok(l) = line {
-                match parse_string(l) {
-                    Command::ChangeDirectory(s) => {
-                        if s == ".." {
-                            println!("cd .. (pop {})", directory_size);
-                            if directory_size <= 100000 {
-                                solution += directory_size;
-                            }
-                            if directory_size > 30000000 - (70000000 - 41111105) {
-                                println!("directory_size {}", directory_size);
-                            }
-                            directory_size += directory_stack.pop().unwrap();
-                        } else {
-                            println!("cd <dir> (push {})", directory_size);
-                            directory_stack.push(directory_size);
-                            directory_size = 0;
+/// Joins path components into an absolute path, the root being "/".
+fn path_string(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+/// Something in the transcript that doesn't add up with the filesystem seen so far.
+#[derive(Debug, PartialEq)]
+enum Problem {
+    UnlistedDirectory(String),
+    AboveRoot,
+    FileSizeChanged(String, u64, u64),
+    OutputWithoutListing,
+    UnknownCommand(String),
+    MalformedLine(String),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::UnlistedDirectory(path) => write!(f, "cd into {} which was never listed", path),
+            Problem::AboveRoot => write!(f, "cd .. above the root directory"),
+            Problem::FileSizeChanged(path, old, new) => write!(f, "{} listed with size {} and {}", path, old, new),
+            Problem::OutputWithoutListing => write!(f, "output without a preceding ls"),
+            Problem::UnknownCommand(c) => write!(f, "unknown command {}", c),
+            Problem::MalformedLine(l) => write!(f, "cannot read {:?}", l),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Directory {
+    directories: BTreeMap<String, Directory>,
+    files: BTreeMap<String, u64>,
+}
+
+impl Directory {
+    /// Total size of all files in this directory and its subdirectories.
+    fn size(&self) -> u64 {
+        self.files.values().sum::<u64>()
+            + self.directories.values().map(|d| d.size()).sum::<u64>()
+    }
+
+    fn get(&self, path: &[String]) -> Option<&Directory> {
+        match path.split_first() {
+            None => Some(self),
+            Some((name, rest)) => self.directories.get(name)?.get(rest),
+        }
+    }
+
+    fn get_mut(&mut self, path: &[String]) -> Option<&mut Directory> {
+        match path.split_first() {
+            None => Some(self),
+            Some((name, rest)) => self.directories.get_mut(name)?.get_mut(rest),
+        }
+    }
+
+    /// Returns the directory at `path`, creating missing directories along the way.
+    fn get_or_create(&mut self, path: &[String]) -> &mut Directory {
+        match path.split_first() {
+            None => self,
+            Some((name, rest)) => self.directories.entry(name.clone()).or_default().get_or_create(rest),
+        }
+    }
+
+    /// Collects (absolute path, recursive size) for this directory and every directory below it.
+    fn collect_sizes(&self, path: &mut Vec<String>, sizes: &mut Vec<(String, u64)>) -> u64 {
+        let mut size = self.files.values().sum::<u64>();
+        for (name, d) in self.directories.iter() {
+            path.push(name.clone());
+            size += d.collect_sizes(path, sizes);
+            path.pop();
+        }
+        sizes.push((path_string(path), size));
+        size
+    }
+}
+
+#[derive(Debug, Default)]
+struct Filesystem {
+    root: Directory,
+    cwd: Vec<String>,
+    listing: bool,
+    line: usize,
+    /// Inconsistencies found while replaying, with the line number they were found on.
+    problems: Vec<(usize, Problem)>,
+}
+
+impl Filesystem {
+    fn from_commands<I: IntoIterator<Item=Command>>(commands: I) -> Filesystem {
+        let mut fs = Filesystem::default();
+        for c in commands {
+            fs.apply(c);
+        }
+        fs
+    }
+
+    fn from_file(file: &str) -> Filesystem {
+        let mut commands = Vec::new();
+        if let Ok(lines) = read_lines(file) {
+            for l in lines.map_while(Result::ok) {
+                commands.push(parse_string(l));
+            }
+        }
+        Filesystem::from_commands(commands)
+    }
+
+    fn apply(&mut self, command: Command) {
+        self.line += 1;
+        match command {
+            Command::ChangeDirectory(s) => {
+                self.listing = false;
+                match s.as_str() {
+                    "/" => { self.cwd.clear(); }
+                    ".." => {
+                        if self.cwd.pop().is_none() {
+                            self.report(Problem::AboveRoot);
+                        }
+                    }
+                    name => {
+                        if !self.root.get_or_create(&self.cwd).directories.contains_key(name) {
+                            let mut path = self.cwd.clone();
+                            path.push(name.to_string());
+                            self.report(Problem::UnlistedDirectory(path_string(&path)));
                         }
+                        self.cwd.push(name.to_string());
                     }
-                    Command::File(_, size) => {
-                        println!("file ({})", size);
-                        directory_size += size;
+                }
+                self.root.get_or_create(&self.cwd);
+            }
+            Command::ListDirectory => {
+                self.listing = true;
+            }
+            Command::Directory(name) => {
+                if !self.listing {
+                    self.report(Problem::OutputWithoutListing);
+                }
+                self.root.get_or_create(&self.cwd).directories.entry(name).or_default();
+            }
+            Command::File(name, size) => {
+                if !self.listing {
+                    self.report(Problem::OutputWithoutListing);
+                }
+                if let Some(old) = self.root.get_or_create(&self.cwd).files.insert(name.clone(), size) {
+                    if old != size {
+                        let mut path = self.cwd.clone();
+                        path.push(name);
+                        self.report(Problem::FileSizeChanged(path_string(&path), old, size));
                     }
-                    Command::Directory(_) => {}
-                    Command::ListDirectory => {}
                 }
             }
+            Command::Unknown(c) => {
+                self.listing = false;
+                self.report(Problem::UnknownCommand(c));
+            }
+            Command::Malformed(l) => {
+                self.report(Problem::MalformedLine(l));
+            }
         }
     }
-    println!("cd .. ({})", directory_size);
-    if directory_size < 100000 {
-        solution += directory_size;
+
+    fn report(&mut self, problem: Problem) {
+        self.problems.push((self.line, problem));
+    }
+
+    fn size(&self) -> u64 {
+        self.root.size()
+    }
+
+    /// Absolute path and recursive size of every directory, children before their parents.
+    fn directory_sizes(&self) -> Vec<(String, u64)> {
+        let mut sizes = Vec::new();
+        self.root.collect_sizes(&mut Vec::new(), &mut sizes);
+        sizes
+    }
+
+    /// The smallest directory which frees up enough space for the update when deleted.
+    fn directory_to_delete(&self, total: u64, required: u64) -> Option<(String, u64)> {
+        let missing = required.saturating_sub(total.saturating_sub(self.size()));
+        self.directory_sizes()
+            .into_iter()
+            .filter(|(_, size)| *size >= missing)
+            .min_by_key(|(_, size)| *size)
     }
-    directory_stack.iter().for_each(|a| { println!("{}", a+directory_size)});
-    solution
+}
+
+fn solve(fs: &Filesystem) -> (u64, u64) {
+    let small_directories = fs.directory_sizes()
+        .iter()
+        .map(|(_, size)| *size)
+        .filter(|size| *size <= 100000)
+        .sum();
+    let (_, delete) = fs.directory_to_delete(TOTAL_DISK_SPACE, REQUIRED_DISK_SPACE).unwrap();
+    (small_directories, delete)
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| args.iter().find_map(|arg| arg.strip_prefix(name));
 
-    println!("Solution {}", solution());
+    let filesystem = match option("--from-json=") {
+        Some(file) => Filesystem::from_json(&fs::read_to_string(file).unwrap()).unwrap(),
+        None => Filesystem::from_file("input.txt"),
+    };
+    for (line, problem) in filesystem.problems.iter() {
+        println!("Line {}: {}", line, problem);
+    }
+
+    if args.iter().any(|arg| arg == "--tree") {
+        print!("{}", filesystem.tree());
+    }
+    if args.iter().any(|arg| arg == "--du") {
+        let max_depth = option("--max-depth=").map(|d| d.parse::<usize>().unwrap());
+        let filter = option("--filter=").map(|f| Pattern::new(f).unwrap());
+        for (size, path) in filesystem.du(max_depth, filter.as_ref()) {
+            println!("{}\t{}", size, path);
+        }
+    }
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", filesystem.to_json());
+    }
+
+    let (part1, part2) = solve(&filesystem);
+    println!("Solution part 1: {}", part1);
+    println!("Solution part 2: {}", part2);
+
+    if args.iter().any(|arg| arg == "--shell") {
+        Shell::new(filesystem).run();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Command, Filesystem, parse_string, Problem, solve};
+
+    #[test]
+    fn test_parse_string() {
+        assert_eq!(parse_string(String::from("$ cd /")), Command::ChangeDirectory(String::from("/")));
+        assert_eq!(parse_string(String::from("$ ls")), Command::ListDirectory);
+        assert_eq!(parse_string(String::from("dir a")), Command::Directory(String::from("a")));
+        assert_eq!(parse_string(String::from("14848514 b.txt")), Command::File(String::from("b.txt"), 14848514));
+        assert_eq!(parse_string(String::from("$ rm a")), Command::Unknown(String::from("rm a")));
+        assert_eq!(parse_string(String::from("$ cd")), Command::Malformed(String::from("$ cd")));
+    }
+
+    #[test]
+    fn test_directory_sizes() {
+        let fs = Filesystem::from_file("test.txt");
+        let sizes = fs.directory_sizes();
+        assert_eq!(sizes, vec![
+            (String::from("/a/e"), 584),
+            (String::from("/a"), 94853),
+            (String::from("/d"), 24933642),
+            (String::from("/"), 48381165),
+        ]);
+    }
+
+    #[test]
+    fn test_cd_root() {
+        let fs = Filesystem::from_commands(vec![
+            Command::ChangeDirectory(String::from("a")),
+            Command::ChangeDirectory(String::from("b")),
+            Command::ChangeDirectory(String::from("/")),
+            Command::File(String::from("c"), 10),
+        ]);
+        assert_eq!(fs.root.files.get("c"), Some(&10));
+        assert!(fs.root.directories["a"].directories.contains_key("b"));
+    }
+
+    #[test]
+    fn test_consistent_transcript() {
+        assert_eq!(Filesystem::from_file("test.txt").problems, vec![]);
+        // The input ends with one `cd ..` too many, which the old stack solution relied on
+        assert_eq!(Filesystem::from_file("input.txt").problems, vec![(1020, Problem::AboveRoot)]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let fs = Filesystem::from_commands(vec![
+            "$ cd /", "$ ls", "dir a", "10 b", "$ cd c", "5 d", "$ cd ..", "$ cd ..",
+            "$ cd a", "$ ls", "1 e", "1 e", "2 e", "$ rm e",
+        ].into_iter().map(|l| parse_string(l.to_string())));
+        assert_eq!(fs.problems, vec![
+            (5, Problem::UnlistedDirectory(String::from("/c"))),
+            (6, Problem::OutputWithoutListing),
+            (8, Problem::AboveRoot),
+            (13, Problem::FileSizeChanged(String::from("/a/e"), 1, 2)),
+            (14, Problem::UnknownCommand(String::from("rm e"))),
+        ]);
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        for l in ["", "$", "$ ", "dir", "dir ", "584", "584 ", "i 584", "-1 i"] {
+            assert_eq!(parse_string(l.to_string()), Command::Malformed(l.to_string()), "{:?}", l);
+        }
+        let fs = Filesystem::from_commands(vec!["$ cd /", "$ ls", "dir", "", "10 b"]
+            .into_iter().map(|l| parse_string(l.to_string())));
+        assert_eq!(fs.problems, vec![
+            (3, Problem::MalformedLine(String::from("dir"))),
+            (4, Problem::MalformedLine(String::new())),
+        ]);
+        assert_eq!(fs.root.files.get("b"), Some(&10));
+    }
+
+    #[test]
+    fn test_solution() {
+        assert_eq!(solve(&Filesystem::from_file("test.txt")), (95437, 24933642));
+    }
+
+    #[test]
+    fn test_real_solution() {
+        assert_eq!(solve(&Filesystem::from_file("input.txt")), (1543140, 1117448));
+    }
 }
@@ -0,0 +1,160 @@
+use glob::Pattern;
+
+use crate::{Directory, Filesystem, path_string};
+
+/// A directory entry, as listed by `ls`.
+enum Entry<'a> {
+    Directory(&'a Directory),
+    File(u64),
+}
+
+impl Entry<'_> {
+    fn size(&self) -> u64 {
+        match self {
+            Entry::Directory(d) => d.size(),
+            Entry::File(size) => *size,
+        }
+    }
+}
+
+impl Directory {
+    /// Subdirectories and files merged in name order.
+    fn entries(&self) -> Vec<(&String, Entry<'_>)> {
+        let mut entries: Vec<(&String, Entry)> = self.directories.iter()
+            .map(|(name, d)| (name, Entry::Directory(d)))
+            .chain(self.files.iter().map(|(name, size)| (name, Entry::File(*size))))
+            .collect();
+        entries.sort_by_key(|(name, _)| *name);
+        entries
+    }
+
+    fn write_tree(&self, prefix: &str, out: &mut String) {
+        let entries = self.entries();
+        for (i, (name, entry)) in entries.iter().enumerate() {
+            let last = i == entries.len() - 1;
+            let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            out.push_str(&format!("{}{}{} ({})\n", prefix, branch, name, entry.size()));
+            if let Entry::Directory(d) = entry {
+                d.write_tree(&format!("{}{}", prefix, indent), out);
+            }
+        }
+    }
+
+    fn collect_du(&self, path: &mut Vec<String>, max_depth: Option<usize>, listing: &mut Vec<(u64, String)>) -> u64 {
+        let mut size = 0;
+        let visible = max_depth.is_none_or(|depth| path.len() < depth);
+        for (name, entry) in self.entries() {
+            path.push(name.clone());
+            let entry_size = match entry {
+                Entry::Directory(d) => d.collect_du(path, max_depth, listing),
+                Entry::File(size) => {
+                    if visible {
+                        listing.push((size, path_string(path)));
+                    }
+                    size
+                }
+            };
+            size += entry_size;
+            path.pop();
+        }
+        if max_depth.is_none_or(|depth| path.len() <= depth) {
+            listing.push((size, path_string(path)));
+        }
+        size
+    }
+}
+
+impl Filesystem {
+    /// Renders the filesystem like `tree`, with the recursive size after every name.
+    pub fn tree(&self) -> String {
+        let mut out = format!("/ ({})\n", self.size());
+        self.root.write_tree("", &mut out);
+        out
+    }
+
+    /// Lists files and directories like `du -a`, largest first.
+    ///
+    /// Entries deeper than `max_depth` path components are left out (but still counted in their
+    /// parents), as are entries whose absolute path doesn't match `filter`.
+    pub fn du(&self, max_depth: Option<usize>, filter: Option<&Pattern>) -> Vec<(u64, String)> {
+        self.du_from(&[], max_depth, filter)
+    }
+
+    /// Like `du`, but only for the directory at `path`.
+    pub fn du_from(&self, path: &[String], max_depth: Option<usize>, filter: Option<&Pattern>) -> Vec<(u64, String)> {
+        let mut listing = Vec::new();
+        if let Some(d) = self.root.get(path) {
+            d.collect_du(&mut path.to_vec(), max_depth, &mut listing);
+        }
+        listing.retain(|(_, path)| filter.is_none_or(|p| p.matches(path)));
+        listing.sort_by(|(size_a, path_a), (size_b, path_b)| size_b.cmp(size_a).then(path_a.cmp(path_b)));
+        listing
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.root).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Filesystem> {
+        Ok(Filesystem { root: serde_json::from_str(json)?, ..Default::default() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glob::Pattern;
+
+    use crate::Filesystem;
+
+    #[test]
+    fn test_tree() {
+        let fs = Filesystem::from_file("test.txt");
+        assert_eq!(fs.tree(), "\
+/ (48381165)
+├── a (94853)
+│   ├── e (584)
+│   │   └── i (584)
+│   ├── f (29116)
+│   ├── g (2557)
+│   └── h.lst (62596)
+├── b.txt (14848514)
+├── c.dat (8504156)
+└── d (24933642)
+    ├── d.ext (5626152)
+    ├── d.log (8033020)
+    ├── j (4060174)
+    └── k (7214296)
+");
+    }
+
+    #[test]
+    fn test_du() {
+        let fs = Filesystem::from_file("test.txt");
+        assert_eq!(fs.du(Some(1), None), vec![
+            (48381165, String::from("/")),
+            (24933642, String::from("/d")),
+            (14848514, String::from("/b.txt")),
+            (8504156, String::from("/c.dat")),
+            (94853, String::from("/a")),
+        ]);
+        assert_eq!(fs.du(None, None).len(), 14);
+        assert_eq!(fs.du(None, Some(&Pattern::new("/d/d.*").unwrap())), vec![
+            (8033020, String::from("/d/d.log")),
+            (5626152, String::from("/d/d.ext")),
+        ]);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let fs = Filesystem::from_file("input.txt");
+        let copy = Filesystem::from_json(&fs.to_json()).unwrap();
+        assert_eq!(copy.root, fs.root);
+        assert_eq!(copy.directory_sizes(), fs.directory_sizes());
+    }
+
+    #[test]
+    fn test_large_sizes() {
+        let fs = Filesystem::from_json(r#"{"directories": {}, "files": {"a": 3000000000, "b": 3000000000}}"#).unwrap();
+        assert_eq!(fs.size(), 6000000000);
+    }
+}
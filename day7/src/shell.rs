@@ -0,0 +1,193 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{Command, Filesystem, parse_string, path_string, REQUIRED_DISK_SPACE, TOTAL_DISK_SPACE};
+
+/// A small interactive shell over a reconstructed filesystem.
+///
+/// Prompt lines are parsed with `parse_string` as if they were typed after the `$ ` of the
+/// transcript, so `cd` and `ls` behave like they do there. `rm` only removes entries from the
+/// in-memory tree, which makes it possible to try out deletions before picking one.
+pub struct Shell {
+    fs: Filesystem,
+}
+
+impl Shell {
+    pub fn new(mut fs: Filesystem) -> Shell {
+        fs.cwd.clear();
+        Shell { fs }
+    }
+
+    pub fn run(&mut self) {
+        print!("{} $ ", path_string(&self.fs.cwd));
+        io::stdout().flush().unwrap();
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if line.trim() == "exit" {
+                break;
+            }
+            print!("{}", self.execute(&line));
+            print!("{} $ ", path_string(&self.fs.cwd));
+            io::stdout().flush().unwrap();
+        }
+        println!();
+    }
+
+    /// Runs one command line and returns what it prints.
+    pub fn execute(&mut self, line: &str) -> String {
+        let line = line.trim();
+        if line.is_empty() {
+            return String::new();
+        }
+        match parse_string(format!("$ {}", line)) {
+            Command::ChangeDirectory(dir) => self.cd(&dir),
+            Command::ListDirectory => self.ls(),
+            // The transcript has no home directory, so a bare cd goes to the root
+            Command::Malformed(_) if line == "cd" => self.cd("/"),
+            Command::Unknown(command) => {
+                let args: Vec<&str> = command.split_whitespace().collect();
+                match args.as_slice() {
+                    ["pwd"] => format!("{}\n", path_string(&self.fs.cwd)),
+                    ["du"] => self.du(),
+                    ["find", "-size", size] => self.find(size),
+                    ["rm", name] => self.rm(name),
+                    _ => format!("unknown command: {}\n", command),
+                }
+            }
+            _ => format!("unknown command: {}\n", line),
+        }
+    }
+
+    /// Resolves a relative or absolute path against the current directory.
+    fn resolve(&self, path: &str) -> Vec<String> {
+        let mut resolved = if path.starts_with('/') { Vec::new() } else { self.fs.cwd.clone() };
+        for component in path.split('/') {
+            match component {
+                "" | "." => {}
+                ".." => { resolved.pop(); }
+                name => { resolved.push(name.to_string()); }
+            }
+        }
+        resolved
+    }
+
+    fn cd(&mut self, dir: &str) -> String {
+        let path = self.resolve(dir);
+        if self.fs.root.get(&path).is_none() {
+            return format!("cd: {}: no such directory\n", path_string(&path));
+        }
+        self.fs.cwd = path;
+        String::new()
+    }
+
+    fn ls(&self) -> String {
+        let cwd = self.fs.root.get(&self.fs.cwd).unwrap();
+        let mut out = String::new();
+        for name in cwd.directories.keys() {
+            out.push_str(&format!("dir {}\n", name));
+        }
+        for (name, size) in cwd.files.iter() {
+            out.push_str(&format!("{} {}\n", size, name));
+        }
+        out
+    }
+
+    fn du(&self) -> String {
+        self.fs.du_from(&self.fs.cwd, Some(self.fs.cwd.len() + 1), None)
+            .iter()
+            .map(|(size, path)| format!("{}\t{}\n", size, path))
+            .collect()
+    }
+
+    /// Lists everything below the current directory larger (`+N`) or smaller (`-N`) than N.
+    fn find(&self, size: &str) -> String {
+        let (larger, limit) = match (size.strip_prefix('+'), size.strip_prefix('-')) {
+            (Some(n), _) => (true, n),
+            (_, Some(n)) => (false, n),
+            _ => (true, size),
+        };
+        let limit = match limit.parse::<u64>() {
+            Ok(limit) => limit,
+            Err(_) => return format!("find: invalid size {}\n", size),
+        };
+        self.fs.du_from(&self.fs.cwd, None, None)
+            .iter()
+            .filter(|(s, _)| if larger { *s > limit } else { *s < limit })
+            .map(|(size, path)| format!("{}\t{}\n", size, path))
+            .collect()
+    }
+
+    /// Removes a file or directory from the in-memory tree and reports the space it frees.
+    fn rm(&mut self, name: &str) -> String {
+        let path = self.resolve(name);
+        let Some((name, parent)) = path.split_last() else {
+            return String::from("rm: refusing to remove /\n");
+        };
+        let freed = match self.fs.root.get_mut(parent) {
+            Some(parent) => {
+                if let Some(d) = parent.directories.remove(name) {
+                    d.size()
+                } else if let Some(size) = parent.files.remove(name) {
+                    size
+                } else {
+                    return format!("rm: {}: no such file or directory\n", path_string(&path));
+                }
+            }
+            None => return format!("rm: {}: no such file or directory\n", path_string(&path)),
+        };
+        // Stay inside the tree when the current directory was removed
+        while self.fs.root.get(&self.fs.cwd).is_none() {
+            self.fs.cwd.pop();
+        }
+
+        let free = TOTAL_DISK_SPACE.saturating_sub(self.fs.size());
+        format!(
+            "removed {}, freed {}, {} free ({} for the update)\n",
+            path_string(&path),
+            freed,
+            free,
+            if free >= REQUIRED_DISK_SPACE { "enough" } else { "not enough" }
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Filesystem;
+    use crate::shell::Shell;
+
+    #[test]
+    fn test_navigation() {
+        let mut shell = Shell::new(Filesystem::from_file("test.txt"));
+        assert_eq!(shell.execute("pwd"), "/\n");
+        assert_eq!(shell.execute("cd a/e"), "");
+        assert_eq!(shell.execute("pwd"), "/a/e\n");
+        assert_eq!(shell.execute("ls"), "584 i\n");
+        assert_eq!(shell.execute("cd ../.."), "");
+        assert_eq!(shell.execute("ls"), "dir a\ndir d\n14848514 b.txt\n8504156 c.dat\n");
+        assert_eq!(shell.execute("cd x"), "cd: /x: no such directory\n");
+        assert_eq!(shell.execute("cd d"), "");
+        assert_eq!(shell.execute("cd"), "");
+        assert_eq!(shell.execute("pwd"), "/\n");
+    }
+
+    #[test]
+    fn test_du_and_find() {
+        let mut shell = Shell::new(Filesystem::from_file("test.txt"));
+        shell.execute("cd a");
+        assert_eq!(shell.execute("du"), "94853\t/a\n62596\t/a/h.lst\n29116\t/a/f\n2557\t/a/g\n584\t/a/e\n");
+        shell.execute("cd /");
+        assert_eq!(shell.execute("find -size +20000000"), "48381165\t/\n24933642\t/d\n");
+        assert_eq!(shell.execute("find -size -1000"), "584\t/a/e\n584\t/a/e/i\n");
+        assert_eq!(shell.execute("find -size 5 extra"), "unknown command: find -size 5 extra\n");
+    }
+
+    #[test]
+    fn test_rm() {
+        let mut shell = Shell::new(Filesystem::from_file("test.txt"));
+        shell.execute("cd a/e");
+        assert_eq!(shell.execute("rm /a"), "removed /a, freed 94853, 21713688 free (not enough for the update)\n");
+        assert_eq!(shell.execute("pwd"), "/\n");
+        assert_eq!(shell.execute("rm d"), "removed /d, freed 24933642, 46647330 free (enough for the update)\n");
+        assert_eq!(shell.execute("rm d"), "rm: /d: no such file or directory\n");
+        assert_eq!(shell.execute("rm /"), "rm: refusing to remove /\n");
+    }
+}
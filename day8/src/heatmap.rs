@@ -0,0 +1,44 @@
+pub enum HeatmapFormat {
+    /// One line per row of comma separated scores.
+    Csv,
+    /// Plain (P2) greymap with the scores scaled to 0-255.
+    Pgm,
+}
+
+/// Renders a `grid[x][y]` of scores as a heatmap file.
+pub fn heatmap(grid: &[Vec<u64>], format: HeatmapFormat) -> String {
+    let width = grid.len();
+    let height = grid.first().map_or(0, |column| column.len());
+    let max = grid.iter().flatten().copied().max().unwrap_or(0).max(1);
+
+    let mut out = String::new();
+    if let HeatmapFormat::Pgm = format {
+        out.push_str(&format!("P2\n{} {}\n255\n", width, height));
+    }
+    for y in 0..height {
+        let row: Vec<String> = grid.iter()
+            .map(|column| match format {
+                HeatmapFormat::Csv => column[y].to_string(),
+                HeatmapFormat::Pgm => (column[y] * 255 / max).to_string(),
+            })
+            .collect();
+        match format {
+            HeatmapFormat::Csv => out.push_str(&row.join(",")),
+            HeatmapFormat::Pgm => out.push_str(&row.join(" ")),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::heatmap::{heatmap, HeatmapFormat};
+
+    #[test]
+    fn test_heatmap() {
+        let grid = vec![vec![0, 4], vec![2, 8], vec![1, 0]];
+        assert_eq!(heatmap(&grid, HeatmapFormat::Csv), "0,2,1\n4,8,0\n");
+        assert_eq!(heatmap(&grid, HeatmapFormat::Pgm), "P2\n3 2\n255\n0 63 31\n127 255 0\n");
+    }
+}
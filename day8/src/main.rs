@@ -1,143 +1,278 @@
 use std::cmp::{max, min};
-use std::env;
+use std::{env, fmt, fs};
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+use crate::heatmap::{heatmap, HeatmapFormat};
+use crate::survey::{AXES, get_scenic_score_in, highest_surrounding_tree_in, is_tree_covered_in, Observer, parse_directions, visible_trees_in};
+
+mod heatmap;
+mod survey;
+
 fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>> {
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
 }
 
-fn read_input() -> [[u32; 99]; 99] {
-    let mut map = [[0; 99]; 99];
-    if let Ok(lines) = read_lines("input.txt") {
-        let mut y = 0;
-        for line in lines {
-            if let Ok(l) = line {
-                for (x, c) in l.chars().enumerate() {
-                    map[x][y] = c.to_digit(10).unwrap();
-                }
-                y += 1;
+/// Why the tree heights could not be read.
+#[derive(Debug, PartialEq)]
+enum MapError {
+    /// A row with a different length than the first one, with both lengths
+    RaggedRow(usize, usize),
+    InvalidHeight(char),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::RaggedRow(expected, found) => write!(f, "row has {} trees instead of {}", found, expected),
+            MapError::InvalidHeight(c) => write!(f, "invalid height {:?}", c),
+        }
+    }
+}
+
+/// Reads the rows of tree heights, which must all have the same length. Errors carry the line number.
+fn parse_lines<I: Iterator<Item=String>>(lines: I) -> Result<Vec<Vec<u32>>, (usize, MapError)> {
+    let mut map: Vec<Vec<u32>> = Vec::new();
+    for (y, l) in lines.enumerate() {
+        let line = y + 1;
+        if y > 0 && l.chars().count() != map.len() {
+            return Err((line, MapError::RaggedRow(map.len(), l.chars().count())));
+        }
+        for (x, c) in l.chars().enumerate() {
+            if map.len() <= x {
+                map.push(Vec::new());
             }
+            map[x].push(c.to_digit(10).ok_or((line, MapError::InvalidHeight(c)))?);
         }
     }
-    map
+    Ok(map)
 }
 
-fn highest_tree_in_row(map: &[[u32; 99]], x: usize, y: usize) -> u32 {
+/// Reads the tree heights as `map[x][y]`, for a grid of any width and height.
+fn read_input(file: &str) -> Vec<Vec<u32>> {
+    match read_lines(file) {
+        Ok(lines) => parse_lines(lines.map_while(Result::ok))
+            .unwrap_or_else(|(line, e)| panic!("{}:{}: {}", file, line, e)),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn width(map: &[Vec<u32>]) -> usize {
+    map.len()
+}
+
+fn height(map: &[Vec<u32>]) -> usize {
+    map.first().map_or(0, |column| column.len())
+}
+
+fn is_edge(map: &[Vec<u32>], x: usize, y: usize) -> bool {
+    x == 0 || y == 0 || x == width(map) - 1 || y == height(map) - 1
+}
+
+// The highest_* functions are only defined for trees that are not on the edge
+
+fn highest_tree_in_row(map: &[Vec<u32>], x: usize, y: usize) -> u32 {
     min(
         *map[x][0..y].iter().max().unwrap(),
-        *map[x][y + 1..99].iter().max().unwrap(),
+        *map[x][y + 1..].iter().max().unwrap(),
     )
 }
 
-fn highest_tree_in_column(map: &[[u32; 99]], x: usize, y: usize) -> u32 {
+fn highest_tree_in_column(map: &[Vec<u32>], x: usize, y: usize) -> u32 {
     min(
         map[0..x].iter().fold(0, |p, row| { max(row[y], p) }),
-        map[x + 1..99].iter().fold(0, |p, row| { max(row[y], p) }),
+        map[x + 1..].iter().fold(0, |p, row| { max(row[y], p) }),
     )
 }
 
-fn highest_surrounding_tree(map: &[[u32; 99]], x: usize, y: usize) -> u32 {
+fn highest_surrounding_tree(map: &[Vec<u32>], x: usize, y: usize) -> u32 {
     min(
         highest_tree_in_column(map, x, y),
         highest_tree_in_row(map, x, y),
     )
 }
 
-fn is_tree_covered(map: &[[u32; 99]], x: usize, y: usize) -> bool {
-    highest_surrounding_tree(&map, x, y) >= map[x][y]
+fn is_tree_covered(map: &[Vec<u32>], x: usize, y: usize) -> bool {
+    !is_edge(map, x, y) && highest_surrounding_tree(map, x, y) >= map[x][y]
 }
 
-fn get_scenic_score(map: &[[u32; 99]], x: usize, y: usize) -> u32 {
-    let mut n = 0;
-    let mut s = 0;
-    let mut w = 0;
-    let mut e = 0;
+/// Marks every tree that is visible from outside the grid, sweeping each row and column once
+/// from both ends while keeping track of the highest tree seen so far.
+fn visible_trees(map: &[Vec<u32>]) -> Vec<Vec<bool>> {
+    let (w, h) = (width(map), height(map));
+    let mut visible = vec![vec![false; h]; w];
 
-    for i in (0..x).rev() {
-        if map[i][y] > map[x][y] {
-            break;
-        }
-        w = x - i;
-        if map[i][y] == map[x][y] {
-            break;
+    let mut sweep = |positions: &mut dyn Iterator<Item=(usize, usize)>| {
+        let mut highest = None;
+        for (x, y) in positions {
+            if highest.is_none_or(|highest| map[x][y] > highest) {
+                visible[x][y] = true;
+                highest = Some(map[x][y]);
+            }
         }
+    };
+    for y in 0..h {
+        sweep(&mut (0..w).map(|x| (x, y)));
+        sweep(&mut (0..w).rev().map(|x| (x, y)));
     }
-
-    for i in x + 1..99 {
-        if map[i][y] > map[x][y] {
-            break;
-        }
-        e = i - x;
-        if map[i][y] == map[x][y] {
-            break;
-        }
+    for x in 0..w {
+        sweep(&mut (0..h).map(|y| (x, y)));
+        sweep(&mut (0..h).rev().map(|y| (x, y)));
     }
+    visible
+}
 
-    for i in y + 1..99 {
-        if map[x][i] > map[x][y] {
-            break;
-        }
-        s = i - y;
-        if map[x][i] == map[x][y] {
-            break;
+/// Scenic score of a single tree, walking from it in all four directions.
+fn get_scenic_score(map: &[Vec<u32>], x: usize, y: usize) -> u64 {
+    get_scenic_score_in(map, x, y, &AXES)
+}
+
+/// Scenic score of every tree.
+///
+/// Each row and column is swept once from both ends while keeping a stack of the trees that
+/// are still in view, from high to low. The viewing distance of a tree is then the distance
+/// to the first tree on the stack which is at least as high, or to the edge if there is none.
+fn scenic_scores(map: &[Vec<u32>]) -> Vec<Vec<u64>> {
+    let (w, h) = (width(map), height(map));
+    let mut scores = vec![vec![1; h]; w];
+
+    let mut sweep = |positions: &mut dyn Iterator<Item=(usize, usize)>| {
+        let mut stack: Vec<(usize, u32)> = Vec::new();
+        for (i, (x, y)) in positions.enumerate() {
+            while stack.last().is_some_and(|(_, height)| *height < map[x][y]) {
+                stack.pop();
+            }
+            let distance = stack.last().map_or(i, |(j, _)| i - j);
+            scores[x][y] *= distance as u64;
+            stack.push((i, map[x][y]));
         }
+    };
+    for y in 0..h {
+        sweep(&mut (0..w).map(|x| (x, y)));
+        sweep(&mut (0..w).rev().map(|x| (x, y)));
     }
-    for i in (0..y).rev() {
-        if map[x][i] > map[x][y] {
-            break;
-        }
-        n = y - i;
-        if map[x][i] == map[x][y] {
-            break;
-        }
+    for x in 0..w {
+        sweep(&mut (0..h).map(|y| (x, y)));
+        sweep(&mut (0..h).rev().map(|y| (x, y)));
     }
-    (w * n * s * e) as u32
+    scores
+}
+
+/// The `n` highest scores with their (x, y) coordinates, best first.
+fn most_scenic(scores: &[Vec<u64>], n: usize) -> Vec<(usize, usize, u64)> {
+    let mut trees: Vec<(usize, usize, u64)> = scores.iter()
+        .enumerate()
+        .flat_map(|(x, column)| column.iter().enumerate().map(move |(y, score)| (x, y, *score)))
+        .collect();
+    trees.sort_by(|(xa, ya, a), (xb, yb, b)| b.cmp(a).then((ya, xa).cmp(&(yb, xb))));
+    trees.truncate(n);
+    trees
 }
 
-fn solution(verbose: bool) -> (u32, u32) {
-    let mut tree_count = 0;
-    let mut scenic_score = 0;
-    let map = read_input();
-    for y in 1..98 {
-        for x in 1..98 {
-            if is_tree_covered(&map, x, y) {
-                if verbose { print!("{}", 1) }
-            } else {
-                if verbose { print!("{}", 0) }
-                tree_count += 1;
+fn solution(file: &str, verbose: bool) -> (usize, u64) {
+    let map = read_input(file);
+    let visible = visible_trees(&map);
+    let scores = scenic_scores(&map);
+    if verbose {
+        for y in 0..height(&map) {
+            for column in visible.iter() {
+                print!("{}", if column[y] { 0 } else { 1 });
             }
+            println!();
         }
-        if verbose { println!(); }
-    }
-    for y in 1..98 {
-        for x in 1..98 {
-            scenic_score = max(scenic_score, get_scenic_score(&map, x, y));
-            if verbose { print!("{} ", get_scenic_score(&map, x, y)) }
+        for y in 0..height(&map) {
+            for column in scores.iter() {
+                print!("{} ", column[y]);
+            }
+            println!();
         }
-        if verbose { println!() }
     }
+    let tree_count = visible.iter().flatten().filter(|v| **v).count();
+    let scenic_score = scores.iter().flatten().copied().max().unwrap_or(0);
     (tree_count, scenic_score)
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let verbose = args.iter().find(|&arg| arg == "--verbose").is_some();
+    let verbose = args.iter().any(|arg| arg == "--verbose");
+    let directions = args.iter()
+        .find_map(|arg| arg.strip_prefix("--directions="))
+        .map(|d| parse_directions(d).expect("Directions are axes, diagonals or all"));
 
-    let (x,y) = solution(verbose);
-    println!("Covered trees {}", x + 99 + 99 + 97 + 97);
+    if let Some((x, y)) = args.iter()
+        .find_map(|arg| arg.strip_prefix("--tree="))
+        .and_then(|tree| tree.split_once(',')) {
+        let map = read_input("input.txt");
+        let (x, y) = (x.parse::<usize>().unwrap(), y.parse::<usize>().unwrap());
+        println!("Tree ({}, {}) has height {}", x, y, map[x][y]);
+        match &directions {
+            None => {
+                if !is_edge(&map, x, y) {
+                    println!("Highest surrounding tree: {}", highest_surrounding_tree(&map, x, y));
+                }
+                println!("Covered: {}", is_tree_covered(&map, x, y));
+                println!("Scenic score: {}", get_scenic_score(&map, x, y));
+            }
+            Some(directions) => {
+                if let Some(highest) = highest_surrounding_tree_in(&map, x, y, directions) {
+                    println!("Highest surrounding tree: {}", highest);
+                }
+                println!("Covered: {}", is_tree_covered_in(&map, x, y, directions));
+                println!("Scenic score: {}", get_scenic_score_in(&map, x, y, directions));
+            }
+        }
+        return;
+    }
+
+    if let Some(file) = args.iter().find_map(|arg| arg.strip_prefix("--heatmap=")) {
+        let format = if file.ends_with(".pgm") { HeatmapFormat::Pgm } else { HeatmapFormat::Csv };
+        let scores = scenic_scores(&read_input("input.txt"));
+        fs::write(file, heatmap(&scores, format)).unwrap();
+    }
+    if let Some(n) = args.iter().find_map(|arg| arg.strip_prefix("--top=")) {
+        let scores = scenic_scores(&read_input("input.txt"));
+        for (x, y, score) in most_scenic(&scores, n.parse::<usize>().unwrap()) {
+            println!("({}, {}): {}", x, y, score);
+        }
+    }
+
+    if let Some(directions) = directions {
+        let map = read_input("input.txt");
+        let visible = visible_trees_in(&map, &directions);
+        let scenic_score = (0..width(&map))
+            .flat_map(|x| (0..height(&map)).map(move |y| (x, y)))
+            .map(|(x, y)| get_scenic_score_in(&map, x, y, &directions))
+            .max()
+            .unwrap_or(0);
+        println!("Visible trees {}", visible.iter().flatten().filter(|v| **v).count());
+        println!("Scenic score: {}", scenic_score);
+        return;
+    }
+    if let Some(observer) = args.iter().find_map(|arg| arg.strip_prefix("--observer=")) {
+        let c: Vec<&str> = observer.split(',').collect();
+        let observer = Observer {
+            x: c[0].parse::<i32>().unwrap(),
+            y: c[1].parse::<i32>().unwrap(),
+            height: c[2].parse::<u32>().unwrap(),
+        };
+        println!("Visible trees {}", observer.count_visible(&read_input("input.txt")));
+        return;
+    }
+
+    let (x, y) = solution("input.txt", verbose);
+    println!("Visible trees {}", x);
     println!("Scenic score: {}", y);
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{highest_tree_in_row, is_tree_covered, read_input};
+    use crate::{get_scenic_score, highest_tree_in_row, is_tree_covered, MapError, most_scenic, parse_lines, read_input, scenic_scores, solution, visible_trees};
 
     #[test]
     fn test_highest_tree_in_row() {
-        let m = read_input();
+        let m = read_input("input.txt");
         assert_eq!(highest_tree_in_row(&m, 1, 1), 2);
         assert_eq!(highest_tree_in_row(&m, 2, 2), 2);
         assert_eq!(highest_tree_in_row(&m, 3, 3), 2);
@@ -145,11 +280,81 @@ mod test {
 
     #[test]
     fn test_is_tree_covered() {
-        let m = read_input();
-        assert_eq!(is_tree_covered(&m, 1, 1), true);
-        assert_eq!(is_tree_covered(&m, 4, 6), false);
-        assert_eq!(is_tree_covered(&m, 4, 7), false);
-        assert_eq!(is_tree_covered(&m, 37, 13), true);
+        let m = read_input("input.txt");
+        assert!(is_tree_covered(&m, 1, 1));
+        assert!(!is_tree_covered(&m, 4, 6));
+        assert!(!is_tree_covered(&m, 4, 7));
+        assert!(is_tree_covered(&m, 37, 13));
         println!("{}", m[37][13]);
     }
+
+    #[test]
+    fn test_visible_trees_matches_is_tree_covered() {
+        let m = read_input("input.txt");
+        let visible = visible_trees(&m);
+        for (x, column) in visible.iter().enumerate() {
+            for (y, v) in column.iter().enumerate() {
+                assert_eq!(*v, !is_tree_covered(&m, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_square_map() {
+        let m = parse_lines(["39795", "92689", "58979"].into_iter().map(String::from)).unwrap();
+        assert_eq!(m.len(), 5);
+        assert_eq!(m[0].len(), 3);
+        assert!(is_tree_covered(&m, 1, 1));
+        assert!(is_tree_covered(&m, 2, 1));
+        assert!(!is_tree_covered(&m, 3, 1));
+        assert_eq!(visible_trees(&m).iter().flatten().filter(|v| **v).count(), 13);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |rows: &[&str]| parse_lines(rows.iter().map(|r| r.to_string()));
+        assert_eq!(parse(&["123", "45", "789"]), Err((2, MapError::RaggedRow(3, 2))));
+        assert_eq!(parse(&["123", "456", "7890"]), Err((3, MapError::RaggedRow(3, 4))));
+        assert_eq!(parse(&["12", "4x"]), Err((2, MapError::InvalidHeight('x'))));
+        assert_eq!(parse(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_solution() {
+        assert_eq!(solution("test_input.txt", false), (21, 8));
+    }
+
+    #[test]
+    fn test_scenic_scores_matches_get_scenic_score() {
+        let m = read_input("input.txt");
+        let scores = scenic_scores(&m);
+        for (x, column) in scores.iter().enumerate() {
+            for (y, score) in column.iter().enumerate() {
+                assert_eq!(*score, get_scenic_score(&m, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_blocking_tree_in_view() {
+        // The taller tree that blocks the view is itself seen, so it counts towards the distance
+        let m = parse_lines(["99999", "99599", "99999"].into_iter().map(String::from)).unwrap();
+        assert_eq!(get_scenic_score(&m, 2, 1), 1);
+        let m = parse_lines(["39795", "92689", "58979"].into_iter().map(String::from)).unwrap();
+        assert_eq!(get_scenic_score(&m, 2, 1), 2);
+        assert_eq!(scenic_scores(&m)[2][1], 2);
+    }
+
+    #[test]
+    fn test_most_scenic() {
+        let scores = scenic_scores(&read_input("test_input.txt"));
+        assert_eq!(scores[2][3], 8);
+        assert_eq!(scores[2][1], 4);
+        assert_eq!(most_scenic(&scores, 2), vec![(2, 3, 8), (1, 2, 6)]);
+    }
+
+    #[test]
+    fn test_real_solution() {
+        assert_eq!(solution("input.txt", false), (1763, 671160));
+    }
 }
@@ -0,0 +1,200 @@
+use crate::{height, width};
+
+/// A step between two trees, `(dx, dy)`. Any step works, not only the eight compass directions,
+/// so a ray in direction `(2, 1)` visits every tree two columns right and one row down.
+pub type Direction = (i32, i32);
+
+pub const AXES: [Direction; 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const DIAGONALS: [Direction; 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+pub const ALL_DIRECTIONS: [Direction; 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+pub fn parse_directions(name: &str) -> Option<Vec<Direction>> {
+    match name {
+        "axes" => Some(AXES.to_vec()),
+        "diagonals" => Some(DIAGONALS.to_vec()),
+        "all" => Some(ALL_DIRECTIONS.to_vec()),
+        _ => None,
+    }
+}
+
+/// Trees met when walking from (x, y) in `direction` until leaving the map, not including (x, y).
+fn ray(map: &[Vec<u32>], x: usize, y: usize, (dx, dy): Direction) -> impl Iterator<Item=(usize, usize)> {
+    let (w, h) = (width(map) as i32, height(map) as i32);
+    (1..)
+        .map(move |i| (x as i32 + i * dx, y as i32 + i * dy))
+        .take_while(move |(x, y)| (dx, dy) != (0, 0) && *x >= 0 && *y >= 0 && *x < w && *y < h)
+        .map(|(x, y)| (x as usize, y as usize))
+}
+
+/// Highest tree between (x, y) and the edge in `direction`, `None` for a tree on that edge.
+pub fn highest_tree_towards(map: &[Vec<u32>], x: usize, y: usize, direction: Direction) -> Option<u32> {
+    ray(map, x, y, direction).map(|(x, y)| map[x][y]).max()
+}
+
+/// Like `highest_surrounding_tree`, but looking in any set of directions.
+pub fn highest_surrounding_tree_in(map: &[Vec<u32>], x: usize, y: usize, directions: &[Direction]) -> Option<u32> {
+    directions.iter()
+        .map(|d| highest_tree_towards(map, x, y, *d))
+        .min()
+        .flatten()
+}
+
+pub fn is_tree_covered_in(map: &[Vec<u32>], x: usize, y: usize, directions: &[Direction]) -> bool {
+    highest_surrounding_tree_in(map, x, y, directions).is_some_and(|highest| highest >= map[x][y])
+}
+
+/// Marks every tree visible from outside the grid in at least one of `directions`.
+///
+/// For each direction the highest tree beyond every position is filled in so that the
+/// neighbour in that direction is always done first, which keeps this O(n) per direction.
+pub fn visible_trees_in(map: &[Vec<u32>], directions: &[Direction]) -> Vec<Vec<bool>> {
+    let (w, h) = (width(map), height(map));
+    let mut visible = vec![vec![false; h]; w];
+    for &(dx, dy) in directions {
+        let mut beyond: Vec<Vec<Option<u32>>> = vec![vec![None; h]; w];
+        let xs: Vec<usize> = if dx > 0 { (0..w).rev().collect() } else { (0..w).collect() };
+        let ys: Vec<usize> = if dy > 0 { (0..h).rev().collect() } else { (0..h).collect() };
+        for &x in xs.iter() {
+            for &y in ys.iter() {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                if (dx, dy) != (0, 0) && nx >= 0 && ny >= 0 && nx < w as i32 && ny < h as i32 {
+                    let (nx, ny) = (nx as usize, ny as usize);
+                    beyond[x][y] = Some(beyond[nx][ny].map_or(map[nx][ny], |b| b.max(map[nx][ny])));
+                }
+                if beyond[x][y].is_none_or(|highest| highest < map[x][y]) {
+                    visible[x][y] = true;
+                }
+            }
+        }
+    }
+    visible
+}
+
+/// Like `get_scenic_score`, but multiplying the viewing distances in any set of directions.
+/// The viewing distance is the number of trees seen along the ray.
+pub fn get_scenic_score_in(map: &[Vec<u32>], x: usize, y: usize, directions: &[Direction]) -> u64 {
+    directions.iter()
+        .map(|d| {
+            let mut distance = 0;
+            for (tx, ty) in ray(map, x, y, *d) {
+                distance += 1;
+                if map[tx][ty] >= map[x][y] {
+                    break;
+                }
+            }
+            distance
+        })
+        .product()
+}
+
+/// Someone looking at the trees from outside the grid, with the eyes `height` above the ground.
+pub struct Observer {
+    pub x: i32,
+    pub y: i32,
+    pub height: u32,
+}
+
+impl Observer {
+    /// Whether the top of tree (x, y) can be seen.
+    ///
+    /// The sight line follows the grid cells picked by Bresenham's line algorithm, and rises or
+    /// falls linearly from the observer to the tree top. A tree reaching the sight line blocks it.
+    pub fn can_see(&self, map: &[Vec<u32>], x: usize, y: usize) -> bool {
+        let (tx, ty) = (x as i64, y as i64);
+        let (ox, oy) = (self.x as i64, self.y as i64);
+        let (dx, dy) = ((tx - ox).abs(), -(ty - oy).abs());
+        let (sx, sy) = ((tx - ox).signum(), (ty - oy).signum());
+        let steps = dx.max(-dy);
+        let (h0, h1) = (self.height as i64, map[x][y] as i64);
+
+        let (mut cx, mut cy, mut err) = (ox, oy, dx + dy);
+        for i in 1..steps {
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                cx += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                cy += sy;
+            }
+            if cx < 0 || cy < 0 || cx >= width(map) as i64 || cy >= height(map) as i64 {
+                continue;
+            }
+            // Compare heights scaled by `steps` to stay in integers
+            let sight = h0 * (steps - i) + h1 * i;
+            if map[cx as usize][cy as usize] as i64 * steps >= sight {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn count_visible(&self, map: &[Vec<u32>]) -> usize {
+        (0..width(map))
+            .flat_map(|x| (0..height(map)).map(move |y| (x, y)))
+            .filter(|(x, y)| self.can_see(map, *x, *y))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{get_scenic_score, is_tree_covered, parse_lines, read_input, visible_trees};
+    use crate::survey::{ALL_DIRECTIONS, AXES, DIAGONALS, get_scenic_score_in, highest_surrounding_tree_in, is_tree_covered_in, Observer, visible_trees_in};
+
+    #[test]
+    fn test_axes_match_original() {
+        let m = read_input("input.txt");
+        assert_eq!(visible_trees_in(&m, &AXES), visible_trees(&m));
+        for x in 0..m.len() {
+            for y in 0..m[0].len() {
+                assert_eq!(is_tree_covered_in(&m, x, y, &AXES), is_tree_covered(&m, x, y));
+                assert_eq!(get_scenic_score_in(&m, x, y, &AXES), get_scenic_score(&m, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_diagonals() {
+        let m = read_input("test_input.txt");
+        // The 4 at (3, 3) is hidden along the axes, but seen over the 0 in the corner
+        assert!(is_tree_covered(&m, 3, 3));
+        assert_eq!(highest_surrounding_tree_in(&m, 3, 3, &DIAGONALS), Some(0));
+        assert!(!is_tree_covered_in(&m, 3, 3, &DIAGONALS));
+        assert!(is_tree_covered_in(&m, 2, 2, &DIAGONALS));
+        assert_eq!(highest_surrounding_tree_in(&m, 0, 2, &DIAGONALS), None);
+        assert_eq!(get_scenic_score_in(&m, 2, 2, &DIAGONALS), 2);
+        assert_eq!(get_scenic_score_in(&m, 2, 3, &ALL_DIRECTIONS), 16);
+
+        let visible = visible_trees_in(&m, &ALL_DIRECTIONS);
+        for (x, column) in visible.iter().enumerate() {
+            for (y, v) in column.iter().enumerate() {
+                assert_eq!(*v, !is_tree_covered_in(&m, x, y, &ALL_DIRECTIONS));
+            }
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_ray() {
+        let m = read_input("test_input.txt");
+        // From (0, 0) in steps of (2, 1) the ray visits (2, 1) and (4, 2)
+        assert_eq!(highest_surrounding_tree_in(&m, 0, 0, &[(2, 1)]), Some(5));
+        assert_eq!(get_scenic_score_in(&m, 0, 0, &[(2, 1)]), 1);
+        assert_eq!(get_scenic_score_in(&m, 2, 1, &[(-2, -1), (2, 1)]), 1);
+    }
+
+    #[test]
+    fn test_observer() {
+        let m = parse_lines(["123", "456", "789"].into_iter().map(String::from)).unwrap();
+        // Low on the left, only the first column is in plain sight
+        let low = Observer { x: -1, y: 1, height: 0 };
+        assert!(low.can_see(&m, 0, 1));
+        assert!(!low.can_see(&m, 1, 1));
+        // High enough everything is visible
+        let treehouse = Observer { x: -1, y: 1, height: 100 };
+        assert_eq!(treehouse.count_visible(&m), 9);
+        let tower = Observer { x: 1, y: -5, height: 9 };
+        assert!(tower.can_see(&m, 1, 2));
+    }
+}
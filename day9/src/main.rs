@@ -10,14 +10,32 @@ fn read_lines<P: AsRef<Path>>(filename: P) -> io::Result<io::Lines<io::BufReader
 }
 
 fn parse_string(i: &str) -> (&str, u32) {
-    let mut s = i.split(" ");
+    let mut s = i.split(' ');
     (
         s.next().unwrap(),
         s.next().unwrap().parse::<u32>().unwrap()
     )
 }
 
-fn visualize(h: &Position, t: &Vec<Position>, v: &HashSet<(i32, i32)>) {
+/// Parses a head move like `R` or `UL`, with at most one horizontal and one vertical letter.
+fn parse_direction(s: &str) -> Option<Direction> {
+    let mut d = Direction { x: 0, y: 0 };
+    for c in s.chars() {
+        match c {
+            'R' if d.x == 0 => { d.x = 1 }
+            'L' if d.x == 0 => { d.x = -1 }
+            'U' if d.y == 0 => { d.y = 1 }
+            'D' if d.y == 0 => { d.y = -1 }
+            _ => { return None }
+        }
+    }
+    if d == (Direction { x: 0, y: 0 }) {
+        return None;
+    }
+    Some(d)
+}
+
+fn visualize(rope: &Rope, v: &HashSet<(i32, i32)>) {
     // print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
     let mut map = [['.'; 1000]; 1000];
 
@@ -27,11 +45,12 @@ fn visualize(h: &Position, t: &Vec<Position>, v: &HashSet<(i32, i32)>) {
     }
 
     // Plot where tail is now
-    for (i, k) in t.iter().enumerate() {
-        map[(k.x + map.len() as i32 / 2) as usize][(k.y + map.len() as i32 / 2) as usize] = char::from_digit(i as u32, 10).unwrap();
+    for (i, k) in rope.knots().iter().enumerate().skip(1) {
+        map[(k.x + map.len() as i32 / 2) as usize][(k.y + map.len() as i32 / 2) as usize] = char::from_digit(i as u32 % 10, 10).unwrap();
     }
 
     // Plot HEAD
+    let h = rope.head();
     map[(h.x + map.len() as i32 / 2) as usize][(h.y + map.len() as i32 / 2) as usize] = 'h';
 
     let mut min_x = map.len();
@@ -40,8 +59,8 @@ fn visualize(h: &Position, t: &Vec<Position>, v: &HashSet<(i32, i32)>) {
     let mut max_y = 0;
 
     for y in 0..map.len() {
-        for x in 0..map.len() {
-            if map[x][y] != '.' {
+        for (x, column) in map.iter().enumerate() {
+            if column[y] != '.' {
                 min_x = min_x.min(x - 2);
                 min_y = min_y.min(y - 2);
                 max_x = max_x.max(x + 2);
@@ -51,14 +70,14 @@ fn visualize(h: &Position, t: &Vec<Position>, v: &HashSet<(i32, i32)>) {
     }
 
     for y in min_y..max_y {
-        for x in min_x..max_x {
-            print!("{}", map[x][y]);
+        for column in map[min_x..max_x].iter() {
+            print!("{}", column[y]);
         }
         println!();
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Position {
     pub x: i32,
     pub y: i32,
@@ -103,72 +122,211 @@ struct Direction {
     pub y: i32,
 }
 
-fn solution(verbose: usize, number_knots: usize) -> usize {
-    let mut visited_squares = HashSet::new();
-    let mut head_position = Position { x: 0, y: 0 };
-    let mut tail_position = Vec::new();
-    for _ in 0..number_knots {
-        tail_position.push(Position { x: 0, y: 0 });
-    }
-    visited_squares.insert((0, 0));
-
-    if let Ok(lines) = read_lines("input.txt") {
-        for line in lines {
-            if let Ok(l) = line {
-                if verbose >= 2 { println!("{}", l); }
-                // parse string
-                let (direction, steps) = parse_string(&l);
-
-                // calculate move vector
-                let d = match direction {
-                    "R" => { Direction { x: 1, y: 0 } }
-                    "L" => { Direction { x: -1, y: 0 } }
-                    "U" => { Direction { x: 0, y: 1 } }
-                    "D" => { Direction { x: 0, y: -1 } }
-                    _ => { panic!("Invalid direction") }
-                };
-
-                // move head and tail
-                (0..steps).for_each(|_| {
-                    // move head
-                    head_position.move_in_direction(&d);
-                    let mut prev = &head_position;
-                    if verbose >= 2 { println!("Head {:?}", head_position); }
-                    for knot in tail_position.iter_mut() {
-                        // move tail
-                        knot.move_in_direction(&prev.get_direction(&knot));
-
-                        if verbose >= 2 { println!("Knot {:?} {:?}", knot, prev); }
-                        prev = knot;
-                    }
-                    // tag tail position
-                    visited_squares.insert((prev.x, prev.y));
-                });
-                if verbose >= 1 { visualize(&head_position, &tail_position, &visited_squares); }
-            }
+/// How a knot follows the knot in front of it.
+trait FollowRule {
+    /// The step `follower` takes after `leader` has moved.
+    fn get_direction(&self, leader: &Position, follower: &Position) -> Direction;
+
+    /// Whether followers react to where the leader was one tick ago, instead of where it is now.
+    fn lagging(&self) -> bool {
+        false
+    }
+}
+
+/// The puzzle rule: knots always stay touching, diagonally if needed.
+struct Touching {}
+
+impl FollowRule for Touching {
+    fn get_direction(&self, leader: &Position, follower: &Position) -> Direction {
+        leader.get_direction(follower)
+    }
+}
+
+/// Knots may trail up to `slack` cells behind before they start moving.
+struct Slack {
+    slack: u32,
+}
+
+impl FollowRule for Slack {
+    fn get_direction(&self, leader: &Position, follower: &Position) -> Direction {
+        if leader.x.abs_diff(follower.x).max(leader.y.abs_diff(follower.y)) <= self.slack {
+            return Direction { x: 0, y: 0 };
+        }
+        Direction { x: (leader.x - follower.x).signum(), y: (leader.y - follower.y).signum() }
+    }
+}
+
+/// Knots stay touching, but only ever move up, down, left or right. When the leader is off
+/// diagonally the follower closes the larger gap first, horizontally if they are equal.
+struct Orthogonal {}
+
+impl FollowRule for Orthogonal {
+    fn get_direction(&self, leader: &Position, follower: &Position) -> Direction {
+        let (dx, dy) = (leader.x - follower.x, leader.y - follower.y);
+        if dx.abs().max(dy.abs()) <= 1 {
+            Direction { x: 0, y: 0 }
+        } else if dx.abs() >= dy.abs() {
+            Direction { x: dx.signum(), y: 0 }
+        } else {
+            Direction { x: 0, y: dy.signum() }
+        }
+    }
+}
+
+/// Any rule, but applied to the positions of the previous tick.
+struct Lagging {
+    rule: Box<dyn FollowRule>,
+}
+
+impl FollowRule for Lagging {
+    fn get_direction(&self, leader: &Position, follower: &Position) -> Direction {
+        self.rule.get_direction(leader, follower)
+    }
+
+    fn lagging(&self) -> bool {
+        true
+    }
+}
+
+/// Parses `touching`, `slack=K` or `orthogonal`.
+fn parse_rule(s: &str) -> Option<Box<dyn FollowRule>> {
+    match s.split_once('=') {
+        None if s == "touching" => Some(Box::new(Touching {})),
+        None if s == "orthogonal" => Some(Box::new(Orthogonal {})),
+        Some(("slack", k)) => Some(Box::new(Slack { slack: k.parse::<u32>().ok()? })),
+        _ => None,
+    }
+}
+
+/// A rope of knots where the first knot is the head and every other knot follows the one
+/// in front of it. Every position visited is recorded for each knot separately.
+struct Rope {
+    knots: Vec<Position>,
+    visited: Vec<HashSet<(i32, i32)>>,
+    rule: Box<dyn FollowRule>,
+}
+
+impl Rope {
+    pub fn new(number_knots: usize, rule: Box<dyn FollowRule>) -> Rope {
+        assert!(number_knots > 0, "A rope needs at least a head");
+        let mut visited = vec![HashSet::new(); number_knots];
+        visited.iter_mut().for_each(|v| { v.insert((0, 0)); });
+        Rope { knots: vec![Position { x: 0, y: 0 }; number_knots], visited, rule }
+    }
+
+    pub fn head(&self) -> &Position {
+        &self.knots[0]
+    }
+
+    pub fn knots(&self) -> &[Position] {
+        &self.knots
+    }
+
+    /// Squares visited by knot `i`, counting the head as knot 0.
+    pub fn visited(&self, i: usize) -> &HashSet<(i32, i32)> {
+        &self.visited[i]
+    }
+
+    /// Moves the head one step, lets the rest of the rope follow and returns the new positions.
+    pub fn step(&mut self, d: &Direction) -> &[Position] {
+        let previous = self.knots.clone();
+        self.knots[0].move_in_direction(d);
+        for i in 1..self.knots.len() {
+            let leader = if self.rule.lagging() { &previous[i - 1] } else { &self.knots[i - 1] };
+            let direction = self.rule.get_direction(leader, &self.knots[i]);
+            self.knots[i].move_in_direction(&direction);
+        }
+        for (knot, visited) in self.knots.iter().zip(self.visited.iter_mut()) {
+            visited.insert((knot.x, knot.y));
         }
+        &self.knots
     }
-    visited_squares.len()
+}
+
+fn solution(file: &str, verbose: usize, number_knots: usize, rule: Box<dyn FollowRule>) -> usize {
+    let mut rope = Rope::new(number_knots + 1, rule);
+
+    if let Ok(lines) = read_lines(file) {
+        for l in lines.map_while(Result::ok) {
+            if verbose >= 2 { println!("{}", l); }
+            // parse string
+            let (direction, steps) = parse_string(&l);
+
+            // calculate move vector
+            let d = parse_direction(direction).unwrap_or_else(|| panic!("Invalid direction {}", direction));
+
+            // move head and tail
+            (0..steps).for_each(|_| {
+                let knots = rope.step(&d);
+                if verbose >= 2 { println!("Knots {:?}", knots); }
+            });
+            if verbose >= 1 { visualize(&rope, rope.visited(number_knots)); }
+        }
+    }
+    rope.visited(number_knots).len()
 }
 
 fn main() {
     let mut verbose = 0;
     let args: Vec<String> = env::args().collect();
-    if args.iter().find(|&arg| arg == "-v").is_some() {
+    if args.iter().any(|arg| arg == "-v") {
         verbose = 1;
     }
-    if args.iter().find(|&arg| arg == "-vv").is_some() {
+    if args.iter().any(|arg| arg == "-vv") {
         verbose = 2;
     }
 
-    println!("Solution {}", solution(verbose, 1));
-    println!("Solution {}", solution(verbose, 9));
+    let rule = || -> Box<dyn FollowRule> {
+        let rule = parse_rule(args.iter().find_map(|arg| arg.strip_prefix("--rule=")).unwrap_or("touching"))
+            .expect("Rules are touching, slack=K or orthogonal");
+        if args.iter().any(|arg| arg == "--lag") {
+            Box::new(Lagging { rule })
+        } else {
+            rule
+        }
+    };
+
+    println!("Solution {}", solution("input.txt", verbose, 1, rule()));
+    println!("Solution {}", solution("input.txt", verbose, 9, rule()));
 }
 
 
 #[cfg(test)]
 mod test {
-    use crate::{Direction, Position};
+    use crate::{Direction, FollowRule, Lagging, Orthogonal, parse_direction, parse_rule, Position, Rope, Slack, solution, Touching};
+
+    /// Leader, follower and expected step for the cases in `test_get_direction`.
+    fn cases(expected: [(i32, i32); 18]) -> Vec<(Position, Position, Direction)> {
+        let positions = [
+            ((2, 1), (1, 1)), ((1, 2), (1, 1)), ((0, 1), (1, 1)), ((1, 0), (1, 1)),
+            ((1, 1), (2, 2)), ((3, 3), (2, 2)), ((1, 3), (2, 2)), ((3, 1), (2, 2)),
+            ((4, 3), (2, 2)), ((3, 4), (2, 2)),
+            ((0, 1), (2, 2)), ((0, 3), (2, 2)), ((1, 0), (2, 2)),
+            ((4, 2), (2, 2)), ((2, 4), (2, 2)),
+            ((0, 2), (2, 2)), ((2, 0), (2, 2)),
+            ((-9, -16), (-11, -18)),
+        ];
+        positions.iter()
+            .zip(expected.iter())
+            .map(|(((lx, ly), (fx, fy)), (dx, dy))| (Position { x: *lx, y: *ly }, Position { x: *fx, y: *fy }, Direction { x: *dx, y: *dy }))
+            .collect()
+    }
+
+    fn assert_rule(rule: &dyn FollowRule, cases: Vec<(Position, Position, Direction)>) {
+        for (leader, follower, direction) in cases {
+            assert_eq!(rule.get_direction(&leader, &follower), direction, "{:?} following {:?}", follower, leader);
+        }
+    }
+
+    const TOUCHING: [(i32, i32); 18] = [
+        (0, 0), (0, 0), (0, 0), (0, 0),
+        (0, 0), (0, 0), (0, 0), (0, 0),
+        (1, 1), (1, 1),
+        (-1, -1), (-1, 1), (-1, -1),
+        (1, 0), (0, 1),
+        (-1, 0), (0, -1),
+        (1, 1),
+    ];
 
     #[test]
     fn test_get_direction() {
@@ -197,4 +355,102 @@ mod test {
 
         assert_eq!(Position { x: -9, y: -16 }.get_direction(&Position { x: -11, y: -18 }), Direction { x: 1, y: 1 });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_direction() {
+        assert_eq!(parse_direction("R"), Some(Direction { x: 1, y: 0 }));
+        assert_eq!(parse_direction("UL"), Some(Direction { x: -1, y: 1 }));
+        assert_eq!(parse_direction("DR"), Some(Direction { x: 1, y: -1 }));
+        assert_eq!(parse_direction("RD"), Some(Direction { x: 1, y: -1 }));
+        assert_eq!(parse_direction("UD"), None);
+        assert_eq!(parse_direction("RR"), None);
+        assert_eq!(parse_direction("X"), None);
+        assert_eq!(parse_direction(""), None);
+    }
+
+    #[test]
+    fn test_rope() {
+        let mut rope = Rope::new(3, Box::new(Touching {}));
+        let right = Direction { x: 1, y: 0 };
+        assert_eq!(rope.step(&right), &[Position { x: 1, y: 0 }, Position { x: 0, y: 0 }, Position { x: 0, y: 0 }]);
+        assert_eq!(rope.step(&right), &[Position { x: 2, y: 0 }, Position { x: 1, y: 0 }, Position { x: 0, y: 0 }]);
+        assert_eq!(rope.step(&right), &[Position { x: 3, y: 0 }, Position { x: 2, y: 0 }, Position { x: 1, y: 0 }]);
+        assert_eq!(rope.step(&Direction { x: 1, y: 1 }), &[Position { x: 4, y: 1 }, Position { x: 3, y: 1 }, Position { x: 2, y: 1 }]);
+        assert_eq!(rope.visited(0).len(), 5);
+        assert_eq!(rope.visited(1).len(), 4);
+        assert_eq!(rope.visited(2).len(), 3);
+        assert_eq!(rope.knots().last(), Some(&Position { x: 2, y: 1 }));
+    }
+
+    #[test]
+    fn test_solution() {
+        assert_eq!(solution("test.txt", 0, 9, Box::new(Touching {})), 36);
+        assert_eq!(solution("input.txt", 0, 1, Box::new(Touching {})), 6181);
+        assert_eq!(solution("input.txt", 0, 9, Box::new(Touching {})), 2386);
+    }
+
+    #[test]
+    fn test_touching_rule() {
+        assert_rule(&Touching {}, cases(TOUCHING));
+        assert_rule(&Slack { slack: 1 }, cases(TOUCHING));
+    }
+
+    #[test]
+    fn test_slack_rule() {
+        assert_rule(&Slack { slack: 0 }, cases([
+            (1, 0), (0, 1), (-1, 0), (0, -1),
+            (-1, -1), (1, 1), (-1, 1), (1, -1),
+            (1, 1), (1, 1),
+            (-1, -1), (-1, 1), (-1, -1),
+            (1, 0), (0, 1),
+            (-1, 0), (0, -1),
+            (1, 1),
+        ]));
+        assert_rule(&Slack { slack: 2 }, cases([(0, 0); 18]));
+        assert_eq!(Slack { slack: 2 }.get_direction(&Position { x: 5, y: 1 }, &Position { x: 2, y: 2 }), Direction { x: 1, y: -1 });
+    }
+
+    #[test]
+    fn test_orthogonal_rule() {
+        assert_rule(&Orthogonal {}, cases([
+            (0, 0), (0, 0), (0, 0), (0, 0),
+            (0, 0), (0, 0), (0, 0), (0, 0),
+            (1, 0), (0, 1),
+            (-1, 0), (-1, 0), (0, -1),
+            (1, 0), (0, 1),
+            (-1, 0), (0, -1),
+            (1, 0),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert!(parse_rule("touching").is_some());
+        assert!(parse_rule("orthogonal").is_some());
+        assert!(parse_rule("slack=3").is_some());
+        assert!(parse_rule("slack=x").is_none());
+        assert!(parse_rule("stretchy").is_none());
+    }
+
+    #[test]
+    fn test_lagging_rope() {
+        let mut rope = Rope::new(3, Box::new(Lagging { rule: Box::new(Touching {}) }));
+        let right = Direction { x: 1, y: 0 };
+        rope.step(&right);
+        rope.step(&right);
+        // The first knot only sees the head at x = 1, so it doesn't move yet
+        assert_eq!(rope.knots(), &[Position { x: 2, y: 0 }, Position { x: 0, y: 0 }, Position { x: 0, y: 0 }]);
+        rope.step(&right);
+        assert_eq!(rope.knots(), &[Position { x: 3, y: 0 }, Position { x: 1, y: 0 }, Position { x: 0, y: 0 }]);
+        rope.step(&right);
+        assert_eq!(rope.knots(), &[Position { x: 4, y: 0 }, Position { x: 2, y: 0 }, Position { x: 0, y: 0 }]);
+    }
+
+    #[test]
+    fn test_trail_coverage() {
+        let touching = solution("test.txt", 0, 9, Box::new(Touching {}));
+        assert_eq!(touching, 36);
+        assert!(solution("test.txt", 0, 9, Box::new(Slack { slack: 2 })) < touching);
+        assert_eq!(solution("test.txt", 0, 9, Box::new(Slack { slack: 0 })), solution("test.txt", 0, 0, Box::new(Touching {})));
+    }
+}
@@ -0,0 +1,338 @@
+//! Graph searches shared by the days that walk height maps, tunnels and cubes.
+//!
+//! Every search takes the nodes to start from, a closure giving the neighbours of a node
+//! and a closure telling whether a node is the goal. A search stops on the first goal it
+//! settles, or explores everything it can reach if there is none.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Where a search keeps the distance to every node it reached and the node it came from.
+pub trait Store<N> {
+    fn distance(&self, node: &N) -> Option<u64>;
+    fn parent(&self, node: &N) -> Option<N>;
+    fn insert(&mut self, node: N, distance: u64, parent: Option<N>);
+    /// Every reached node with its distance, in no particular order.
+    fn distances(&self) -> Box<dyn Iterator<Item = (N, u64)> + '_>;
+}
+
+impl<N: Clone + Eq + Hash> Store<N> for HashMap<N, (u64, Option<N>)> {
+    fn distance(&self, node: &N) -> Option<u64> {
+        self.get(node).map(|(d, _)| *d)
+    }
+
+    fn parent(&self, node: &N) -> Option<N> {
+        self.get(node)?.1.clone()
+    }
+
+    fn insert(&mut self, node: N, distance: u64, parent: Option<N>) {
+        HashMap::insert(self, node, (distance, parent));
+    }
+
+    fn distances(&self) -> Box<dyn Iterator<Item = (N, u64)> + '_> {
+        Box::new(self.iter().map(|(n, (d, _))| (n.clone(), *d)))
+    }
+}
+
+/// A store for the (x, y) cells of a map, for searches that reach too many cells to hash them all.
+pub struct Grid {
+    width: usize,
+    /// `u64::MAX` for cells not reached
+    distances: Vec<u64>,
+    /// Index of the parent cell, `u32::MAX` for none
+    parents: Vec<u32>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Grid {
+        assert!(width * height < u32::MAX as usize, "{}x{} is too large for a grid", width, height);
+        Grid { width, distances: vec![u64::MAX; width * height], parents: vec![u32::MAX; width * height] }
+    }
+
+    fn index(&self, (x, y): &(usize, usize)) -> usize {
+        assert!(*x < self.width, "{} is outside of the grid", x);
+        y * self.width + x
+    }
+}
+
+impl Store<(usize, usize)> for Grid {
+    fn distance(&self, node: &(usize, usize)) -> Option<u64> {
+        Some(self.distances[self.index(node)]).filter(|d| *d != u64::MAX)
+    }
+
+    fn parent(&self, node: &(usize, usize)) -> Option<(usize, usize)> {
+        let parent = self.parents[self.index(node)] as usize;
+        (parent != u32::MAX as usize).then(|| (parent % self.width, parent / self.width))
+    }
+
+    fn insert(&mut self, node: (usize, usize), distance: u64, parent: Option<(usize, usize)>) {
+        let i = self.index(&node);
+        self.distances[i] = distance;
+        self.parents[i] = parent.map_or(u32::MAX, |p| self.index(&p) as u32);
+    }
+
+    fn distances(&self) -> Box<dyn Iterator<Item = ((usize, usize), u64)> + '_> {
+        Box::new(self.distances.iter()
+            .enumerate()
+            .filter(|(_, d)| **d != u64::MAX)
+            .map(|(i, d)| ((i % self.width, i / self.width), *d)))
+    }
+}
+
+/// What a search found: the distance to every node it reached and the way it got there.
+///
+/// When the search stopped on a goal, the distances of nodes a weighted search had not
+/// settled yet are only upper bounds.
+pub struct Search<N, S = HashMap<N, (u64, Option<N>)>> {
+    pub store: S,
+    /// The goal the search stopped on
+    pub goal: Option<N>,
+}
+
+impl<N: Clone + Eq + Hash> Search<N> {
+    pub fn new() -> Search<N> {
+        Search::with_store(HashMap::default())
+    }
+}
+
+impl<N: Clone + Eq + Hash> Default for Search<N> {
+    fn default() -> Self {
+        Search::new()
+    }
+}
+
+impl<N: Clone, S: Store<N>> Search<N, S> {
+    /// A search keeping what it finds in `store`, which should be empty.
+    pub fn with_store(store: S) -> Search<N, S> {
+        Search { store, goal: None }
+    }
+
+    pub fn distance(&self, node: &N) -> Option<u64> {
+        self.store.distance(node)
+    }
+
+    pub fn reached(&self, node: &N) -> bool {
+        self.store.distance(node).is_some()
+    }
+
+    /// Every reached node with its distance, in no particular order.
+    pub fn distances(&self) -> impl Iterator<Item = (N, u64)> + '_ {
+        self.store.distances()
+    }
+
+    /// The nodes from a start to `node`, both included.
+    pub fn path_to(&self, node: &N) -> Option<Vec<N>> {
+        if !self.reached(node) {
+            return None;
+        }
+        let mut path = vec![node.clone()];
+        while let Some(parent) = self.store.parent(path.last().unwrap()) {
+            path.push(parent);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// The nodes from a start to the goal, if one was found.
+    pub fn path(&self) -> Option<Vec<N>> {
+        self.path_to(self.goal.as_ref()?)
+    }
+
+    /// Breadth first search, where every step costs 1.
+    pub fn bfs<I>(
+        mut self,
+        starts: impl IntoIterator<Item = N>,
+        mut neighbours: impl FnMut(&N) -> I,
+        mut goal: impl FnMut(&N) -> bool,
+    ) -> Search<N, S>
+    where
+        I: IntoIterator<Item = N>,
+    {
+        let mut queue = VecDeque::new();
+        for start in starts {
+            if !self.reached(&start) {
+                self.store.insert(start.clone(), 0, None);
+                queue.push_back((start, 0));
+            }
+        }
+
+        while let Some((node, distance)) = queue.pop_front() {
+            if goal(&node) {
+                self.goal = Some(node);
+                break;
+            }
+            for next in neighbours(&node) {
+                if !self.reached(&next) {
+                    self.store.insert(next.clone(), distance + 1, Some(node.clone()));
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+        self
+    }
+
+    /// Like `dijkstra`, looking first at the nodes the `heuristic` estimates closer to the goal.
+    ///
+    /// The path found is the shortest as long as the heuristic never overestimates the
+    /// remaining cost.
+    pub fn astar<I>(
+        mut self,
+        starts: impl IntoIterator<Item = N>,
+        mut neighbours: impl FnMut(&N) -> I,
+        mut heuristic: impl FnMut(&N) -> u64,
+        mut goal: impl FnMut(&N) -> bool,
+    ) -> Search<N, S>
+    where
+        I: IntoIterator<Item = (N, u64)>,
+    {
+        // The heap only holds indices into `queued`, so nodes do not need to be ordered
+        let mut queued: Vec<(N, u64)> = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for start in starts {
+            if !self.reached(&start) {
+                self.store.insert(start.clone(), 0, None);
+                heap.push(Reverse((heuristic(&start), queued.len())));
+                queued.push((start, 0));
+            }
+        }
+
+        while let Some(Reverse((_, i))) = heap.pop() {
+            let (node, distance) = queued[i].clone();
+            if self.distance(&node).is_some_and(|known| distance > known) {
+                // Found a shorter way since this was queued
+                continue;
+            }
+            if goal(&node) {
+                self.goal = Some(node);
+                break;
+            }
+            for (next, cost) in neighbours(&node) {
+                let d = distance + cost;
+                if self.distance(&next).is_none_or(|known| d < known) {
+                    self.store.insert(next.clone(), d, Some(node.clone()));
+                    heap.push(Reverse((d + heuristic(&next), queued.len())));
+                    queued.push((next, d));
+                }
+            }
+        }
+        self
+    }
+
+    /// Shortest paths where every step has its own cost, given along with each neighbour.
+    pub fn dijkstra<I>(
+        self,
+        starts: impl IntoIterator<Item = N>,
+        neighbours: impl FnMut(&N) -> I,
+        goal: impl FnMut(&N) -> bool,
+    ) -> Search<N, S>
+    where
+        I: IntoIterator<Item = (N, u64)>,
+    {
+        self.astar(starts, neighbours, |_| 0, goal)
+    }
+}
+
+/// Breadth first search from any of the `starts`, stopping on the first `goal` reached.
+pub fn bfs<N, I>(starts: impl IntoIterator<Item = N>, neighbours: impl FnMut(&N) -> I, goal: impl FnMut(&N) -> bool) -> Search<N>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    Search::new().bfs(starts, neighbours, goal)
+}
+
+/// Cheapest paths from any of the `starts`, with neighbours given along with the cost of the step.
+pub fn dijkstra<N, I>(starts: impl IntoIterator<Item = N>, neighbours: impl FnMut(&N) -> I, goal: impl FnMut(&N) -> bool) -> Search<N>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = (N, u64)>,
+{
+    Search::new().dijkstra(starts, neighbours, goal)
+}
+
+/// Cheapest paths from any of the `starts`, guided by a `heuristic` that must not overestimate.
+pub fn astar<N, I>(
+    starts: impl IntoIterator<Item = N>,
+    neighbours: impl FnMut(&N) -> I,
+    heuristic: impl FnMut(&N) -> u64,
+    goal: impl FnMut(&N) -> bool,
+) -> Search<N>
+where
+    N: Clone + Eq + Hash,
+    I: IntoIterator<Item = (N, u64)>,
+{
+    Search::new().astar(starts, neighbours, heuristic, goal)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{astar, bfs, dijkstra, Grid, Search};
+
+    /// Moves on a 10x10 grid, except through the wall at x = 5 that has a gap at y = 9.
+    fn grid((x, y): &(i32, i32)) -> Vec<(i32, i32)> {
+        [(x + 1, *y), (x - 1, *y), (*x, y + 1), (*x, y - 1)]
+            .into_iter()
+            .filter(|(x, y)| (0..10).contains(x) && (0..10).contains(y) && (*x != 5 || *y == 9))
+            .collect()
+    }
+
+    #[test]
+    fn test_bfs() {
+        let search = bfs([(0, 0)], grid, |n| *n == (9, 0));
+        assert_eq!(search.goal, Some((9, 0)));
+        assert_eq!(search.distance(&(9, 0)), Some(27));
+        let path = search.path().unwrap();
+        assert_eq!(path.len(), 28);
+        assert_eq!(path.first(), Some(&(0, 0)));
+        assert!(path.contains(&(5, 9)));
+
+        // Without a goal everything reachable is explored
+        let search = bfs([(0, 0)], grid, |_| false);
+        assert_eq!(search.goal, None);
+        assert_eq!(search.distances().count(), 91);
+        assert!(!search.reached(&(5, 0)));
+        assert_eq!(search.path_to(&(5, 0)), None);
+        assert_eq!(search.path_to(&(0, 0)), Some(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn test_multi_source() {
+        let search = bfs([(0, 0), (9, 0)], grid, |_| false);
+        assert_eq!(search.distance(&(4, 0)), Some(4));
+        assert_eq!(search.distance(&(6, 0)), Some(3));
+        assert_eq!(search.path_to(&(7, 0)), Some(vec![(9, 0), (8, 0), (7, 0)]));
+    }
+
+    #[test]
+    fn test_weighted() {
+        // Going up costs 10, so the cheapest way to (0, 2) goes around through x = 3
+        let cost = |n: &(i32, i32)| grid(n).into_iter().map(|m| (m, if m.1 > n.1 && m.0 < 3 { 10 } else { 1 })).collect::<Vec<_>>();
+        let search = dijkstra([(0, 0)], cost, |n| *n == (0, 2));
+        assert_eq!(search.distance(&(0, 2)), Some(8));
+        assert_eq!(search.path().unwrap().len(), 9);
+
+        let manhattan = |(x, y): &(i32, i32)| (x.abs_diff(0) + y.abs_diff(2)) as u64;
+        let guided = astar([(0, 0)], cost, manhattan, |n| *n == (0, 2));
+        assert_eq!(guided.distance(&(0, 2)), Some(8));
+        assert!(guided.distances().count() <= search.distances().count());
+
+        // With unit costs Dijkstra finds the same distances as BFS
+        let unit = |n: &(i32, i32)| grid(n).into_iter().map(|m| (m, 1));
+        let weighted = dijkstra([(0, 0)], unit, |_| false);
+        let mut distances: Vec<_> = weighted.distances().collect();
+        let mut expected: Vec<_> = bfs([(0, 0)], grid, |_| false).distances().collect();
+        distances.sort();
+        expected.sort();
+        assert_eq!(distances, expected);
+    }
+
+    #[test]
+    fn test_grid() {
+        let unsigned = |&(x, y): &(usize, usize)| grid(&(x as i32, y as i32)).into_iter().map(|(x, y)| (x as usize, y as usize));
+        let search = Search::with_store(Grid::new(10, 10)).bfs([(0, 0)], unsigned, |n| *n == (9, 0));
+        assert_eq!(search.distance(&(9, 0)), Some(27));
+        assert_eq!(search.path(), bfs([(0, 0)], unsigned, |n| *n == (9, 0)).path());
+        assert_eq!(search.distance(&(5, 0)), None);
+        assert_eq!(search.distances().filter(|(_, d)| *d == 1).count(), 2);
+    }
+}